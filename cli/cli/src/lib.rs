@@ -0,0 +1 @@
+pub use crate::ast::param::ParamOverrides;
@@ -1,20 +1,31 @@
 use crate::{
-    ast::key::KeyIdent,
-    lex::TokenType,
+    ast::{
+        key::{KeyIdent, KeyRef},
+        layout::{LogicalLayout, PhysicalLayout},
+        param::ParamKind,
+    },
+    lex::{Token, TokenType},
     parse::{
-        expect_tokens, next_match, parse_optional_trigger_args, parse_sequence_trailing,
-        parse_square_bracket_list, Parse, TokenStream,
+        expect_tokens, next_match, parse_block, parse_optional_trigger_args, parse_sequence,
+        parse_sequence_trailing, parse_square_bracket_list, skip_newline, Parse, TokenStream,
     },
-    utils::Spanned,
+    target::TargetOs,
+    utils::{Span, Spanned},
 };
+use itertools::Itertools;
 use miette::{miette, LabeledSpan, Severity};
+use std::{mem::discriminant, time::Duration};
 
 pub mod key;
+pub mod layout;
+pub mod param;
 
 #[derive(Debug, Clone)]
 pub struct Profile {
     pub name: Spanned<String>,
+    pub metadata: Option<Spanned<Metadata>>,
     pub config: Spanned<Config>,
+    pub toggles: Box<[Spanned<Toggle>]>,
     pub layers: Box<[Spanned<Layer>]>,
 }
 
@@ -26,33 +37,241 @@ impl Parse for Profile {
         expect_tokens(ts, [TokenType::Profile])?;
         let name = String::parse_spanned(ts)?;
         expect_tokens(ts, [TokenType::Newline])?;
+        let metadata = if next_match!(ts, TokenType::Metadata) {
+            let metadata = Metadata::parse_spanned(ts)?;
+            expect_tokens(ts, [TokenType::Newline])?;
+            Some(metadata)
+        } else {
+            None
+        };
+        while next_match!(ts, TokenType::Param) {
+            parse_param_declaration(ts)?;
+            expect_tokens(ts, [TokenType::Newline])?;
+        }
         let config = Config::parse_spanned(ts)?;
         expect_tokens(ts, [TokenType::Newline])?;
+        let mut toggles = Vec::new();
+        while next_match!(ts, TokenType::Toggle) {
+            toggles.push(Toggle::parse_spanned(ts)?);
+            expect_tokens(ts, [TokenType::Newline])?;
+        }
         let layers = parse_sequence_trailing(ts, TokenType::Newline, TokenType::Eof)?;
+
         Ok(Self {
             name,
+            metadata,
             config,
+            toggles: toggles.into_boxed_slice(),
             layers,
         })
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct Toggle {
+    pub name: Spanned<String>,
+    pub initial: bool,
+}
+
+impl Parse for Toggle {
+    fn parse(ts: &mut TokenStream<'_>) -> miette::Result<Self> {
+        expect_tokens(ts, [TokenType::Toggle])?;
+        let name = parse_toggle_name(ts)?;
+        let initial = if next_match!(ts, TokenType::Equals) {
+            expect_tokens(ts, [TokenType::Equals])?;
+            bool::parse(ts)?
+        } else {
+            false
+        };
+        Ok(Self { name, initial })
+    }
+}
+
+fn parse_toggle_name(ts: &mut TokenStream<'_>) -> miette::Result<Spanned<String>> {
+    let [ident_token] = expect_tokens(ts, [TokenType::Ident])?;
+    Ok(Spanned::new(
+        ident_token.bytes().to_string(),
+        ident_token.loc(),
+    ))
+}
+
+/// Parses `param name = value`. The parameter was already substituted by
+/// [`resolve_params`](param::resolve_params), the value is only checked here.
+fn parse_param_declaration(ts: &mut TokenStream<'_>) -> miette::Result<()> {
+    expect_tokens(ts, [TokenType::Param, TokenType::Ident, TokenType::Equals])?;
+    parse_param_value(ts)
+}
+
+fn parse_param_value(ts: &mut TokenStream<'_>) -> miette::Result<()> {
+    if next_match!(ts, TokenType::ParamRef) {
+        return parse_param_ref(ts, &ParamKind::ALL, parse_param_value);
+    }
+    match ts.peek_type().map(ParamKind::of) {
+        Some(ParamKind::String) => String::parse(ts).map(drop),
+        Some(ParamKind::Number | ParamKind::Duration) => Duration::parse(ts).map(drop),
+        _ => KeyRef::parse(ts).map(drop),
+    }
+}
+
+/// Parses a `$name` reference to a parameter and its value, which the resolve pass placed right
+/// after it, if the parameter has one of the accepted types
+fn parse_param_ref<T>(
+    ts: &mut TokenStream<'_>,
+    accepted: &[ParamKind],
+    parse: impl FnOnce(&mut TokenStream<'_>) -> miette::Result<T>,
+) -> miette::Result<T> {
+    let [ref_token] = expect_tokens(ts, [TokenType::ParamRef])?;
+    let name = &ref_token.bytes()[1..];
+    let value_token = ts.peek().copied().filter(|t| t.loc() == ref_token.loc());
+    let Some(value_token) = value_token else {
+        return Err(miette!(
+            severity = Severity::Error,
+            labels = vec![LabeledSpan::new(
+                Some("not declared".to_string()),
+                ref_token.start(),
+                ref_token.bytes().len()
+            )],
+            help = format!("Declare it at the top of the profile with `param {name} = value`"),
+            "Undefined parameter '{}'",
+            name
+        ));
+    };
+
+    let found = ParamKind::of(value_token.kind());
+    if !accepted.contains(&found) {
+        return Err(miette!(
+            severity = Severity::Error,
+            labels = vec![LabeledSpan::new(
+                Some(format!("expected: {}, found: {found}", accepted[0])),
+                ref_token.start(),
+                ref_token.bytes().len()
+            )],
+            help = format!("'{name}' is declared as a {found}"),
+            "Parameter '{}' has the wrong type",
+            name
+        ));
+    }
+    parse(ts)
+}
+
+#[derive(Debug, Clone)]
+pub struct Metadata {
+    pub entries: Box<[Spanned<MetadataEntry>]>,
+}
+
+impl Parse for Metadata {
+    fn parse(ts: &mut TokenStream<'_>) -> miette::Result<Self> {
+        expect_tokens(ts, [TokenType::Metadata])?;
+        Ok(Self {
+            entries: parse_block(ts)?,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum MetadataEntry {
+    Author(Spanned<String>),
+    Version(Spanned<String>),
+    Description(Spanned<String>),
+    Keyboard(Spanned<String>),
+    Tags(Box<[Spanned<String>]>),
+}
+
+impl Parse for MetadataEntry {
+    fn parse(ts: &mut TokenStream<'_>) -> miette::Result<Self> {
+        let [ident_token, _] = expect_tokens(ts, [TokenType::Ident, TokenType::Equals])?;
+        Ok(match ident_token.bytes() {
+            "author" => MetadataEntry::Author(String::parse_spanned(ts)?),
+            "version" => MetadataEntry::Version(String::parse_spanned(ts)?),
+            "description" => MetadataEntry::Description(String::parse_spanned(ts)?),
+            "keyboard" => MetadataEntry::Keyboard(String::parse_spanned(ts)?),
+            "tags" => MetadataEntry::Tags(parse_square_bracket_list(ts)?),
+            _ => {
+                return Err(miette!(
+                    severity = Severity::Error,
+                    labels = vec![LabeledSpan::new(
+                        Some(format!("invalid metadata entry {}", ident_token.bytes())),
+                        ident_token.start(),
+                        ident_token.bytes().len()
+                    )],
+                    help = "Valid entries are author, version, description, keyboard and tags",
+                    "Unexpected metadata entry"
+                ));
+            }
+        })
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Config {
     pub entries: Box<[Spanned<ConfigEntry>]>,
+    /// Entries of `on <os> { ... }` blocks, they override `entries` when targeting that os
+    pub platform_entries: Box<[PlatformEntries]>,
 }
 
+pub type PlatformEntries = (Spanned<TargetOs>, Box<[Spanned<ConfigEntry>]>);
+
 impl Parse for Config {
     fn parse(ts: &mut TokenStream<'_>) -> miette::Result<Self> {
-        expect_tokens(
-            ts,
-            [TokenType::Config, TokenType::LCurly, TokenType::Newline],
-        )?;
-        let entries = parse_sequence_trailing(ts, TokenType::Newline, TokenType::RCurly)?;
+        expect_tokens(ts, [TokenType::Config])?;
+        let items: Box<[Spanned<ConfigItem>]> = parse_block(ts)?;
+
+        let mut entries = Vec::new();
+        let mut platform_entries = Vec::new();
+        for item in items {
+            match item.value {
+                ConfigItem::Entry(entry) => entries.push(Spanned::new(entry, item.span)),
+                ConfigItem::On(platform, platform_items) => {
+                    platform_entries.push((platform, platform_items))
+                }
+            }
+        }
+
+        Ok(Self {
+            entries: entries.into_boxed_slice(),
+            platform_entries: platform_entries.into_boxed_slice(),
+        })
+    }
+}
+
+impl Config {
+    /// Resolves the `on` blocks for the target. Their entries take the place of the general
+    /// entries of the same kind.
+    pub fn for_target(self, target: TargetOs) -> Self {
+        let mut entries: Vec<_> = self
+            .platform_entries
+            .into_iter()
+            .filter(|(platform, _)| platform.value == target)
+            .flat_map(|(_, entries)| entries)
+            .collect();
+        let overridden: Vec<_> = entries.iter().map(|e| discriminant(&e.value)).collect();
+        entries.extend(
+            self.entries
+                .into_iter()
+                .filter(|e| !overridden.contains(&discriminant(&e.value))),
+        );
 
-        expect_tokens(ts, [TokenType::RCurly])?;
+        Self {
+            entries: entries.into_boxed_slice(),
+            platform_entries: Box::new([]),
+        }
+    }
+}
 
-        Ok(Self { entries })
+enum ConfigItem {
+    Entry(ConfigEntry),
+    On(Spanned<TargetOs>, Box<[Spanned<ConfigEntry>]>),
+}
+impl Parse for ConfigItem {
+    fn parse(ts: &mut TokenStream<'_>) -> miette::Result<Self> {
+        if next_match!(ts, TokenType::On) {
+            expect_tokens(ts, [TokenType::On])?;
+            let platform = TargetOs::parse_spanned(ts)?;
+            let entries = parse_block(ts)?;
+            Ok(ConfigItem::On(platform, entries))
+        } else {
+            Ok(ConfigItem::Entry(ConfigEntry::parse(ts)?))
+        }
     }
 }
 
@@ -65,7 +284,10 @@ pub enum ConfigEntry {
     ChordTimeout(Spanned<usize>),
     SequenceTimeout(Spanned<usize>),
     ComboTimeout(Spanned<usize>),
-    Advanced(bool),
+    CapsWordKeys(Box<[Spanned<KeyRef>]>),
+    KeyboardLayout(Spanned<LogicalLayout>),
+    RequirePriorIdle(Spanned<Duration>),
+    Advanced(Spanned<bool>),
 }
 
 impl Parse for ConfigEntry {
@@ -79,7 +301,10 @@ impl Parse for ConfigEntry {
             "chord_timeout" => ConfigEntry::ChordTimeout(usize::parse_spanned(ts)?),
             "sequence_timeout" => ConfigEntry::SequenceTimeout(usize::parse_spanned(ts)?),
             "combo_timeout" => ConfigEntry::ComboTimeout(usize::parse_spanned(ts)?),
-            "advanced" => todo!(),
+            "caps_word_keys" => ConfigEntry::CapsWordKeys(parse_square_bracket_list(ts)?),
+            "keyboard_layout" => ConfigEntry::KeyboardLayout(LogicalLayout::parse_spanned(ts)?),
+            "require_prior_idle" => ConfigEntry::RequirePriorIdle(Duration::parse_spanned(ts)?),
+            "advanced" => ConfigEntry::Advanced(bool::parse_spanned(ts)?),
             _ => {
                 return Err(miette!(
                     severity = Severity::Error,
@@ -103,6 +328,7 @@ impl ConfigEntry {
             | ConfigEntry::ChordTimeout(t)
             | ConfigEntry::SequenceTimeout(t)
             | ConfigEntry::ComboTimeout(t) => Some(t.value),
+            ConfigEntry::RequirePriorIdle(t) => Some(t.as_millis() as usize),
             _ => None,
         }
     }
@@ -111,7 +337,11 @@ impl ConfigEntry {
 impl Config {
     pub fn to_data(&self) -> ConfigData {
         let mut data = ConfigData::default();
+        self.apply(&mut data);
+        data
+    }
 
+    pub fn apply(&self, data: &mut ConfigData) {
         for entry in self.entries.iter().rev() {
             match &entry.value {
                 ConfigEntry::DefaultLayer(v) => data.default_layer = Some(v.value.clone()),
@@ -121,11 +351,14 @@ impl Config {
                 ConfigEntry::ChordTimeout(v) => data.chord_timeout = v.value,
                 ConfigEntry::SequenceTimeout(v) => data.sequence_timeout = v.value,
                 ConfigEntry::ComboTimeout(v) => data.combo_timeout = v.value,
-                ConfigEntry::Advanced(v) => data.advanced = *v,
+                ConfigEntry::CapsWordKeys(keys) => data.caps_word_keys = keys.to_vec(),
+                ConfigEntry::KeyboardLayout(v) => data.keyboard_layout = v.value,
+                ConfigEntry::RequirePriorIdle(v) => {
+                    data.require_prior_idle = v.as_millis() as usize
+                }
+                ConfigEntry::Advanced(v) => data.advanced = v.value,
             }
         }
-
-        data
     }
 }
 
@@ -140,6 +373,9 @@ pub struct ConfigData {
     pub chord_timeout: usize,
     pub sequence_timeout: usize,
     pub combo_timeout: usize,
+    pub caps_word_keys: Vec<Spanned<KeyRef>>,
+    pub keyboard_layout: LogicalLayout,
+    pub require_prior_idle: usize,
     pub advanced: bool,
 }
 
@@ -153,6 +389,15 @@ impl Default for ConfigData {
             chord_timeout: DEFULT_TIMEOUT,
             sequence_timeout: DEFULT_TIMEOUT,
             combo_timeout: DEFULT_TIMEOUT,
+            caps_word_keys: ('a'..='z')
+                .chain(['-'])
+                .map(KeyRef::Char)
+                .chain(KeyIdent::DIGITS.into_iter().map(KeyRef::Pos))
+                .chain([KeyRef::Pos(KeyIdent::Backspace)])
+                .map(|key| Spanned::new(key, Span::default()))
+                .collect(),
+            keyboard_layout: LogicalLayout::default(),
+            require_prior_idle: 0,
             advanced: false,
         }
     }
@@ -173,47 +418,513 @@ impl Parse for Behavior {
             Some(TokenType::Capture) => Ok(Behavior::Capture),
             Some(TokenType::Release) => Ok(Behavior::Release),
             Some(TokenType::Wait) => Ok(Behavior::Wait),
-            Some(_) => {
+            Some(_) => Err(miette!(
+                severity = Severity::Error,
+                labels = vec![LabeledSpan::new(
+                    Some(format!(
+                        "expected: behavior, found: {}",
+                        next_token.unwrap().kind()
+                    )),
+                    next_token.unwrap().start(),
+                    next_token.unwrap().bytes().len()
+                )],
+                "Unexpected token found"
+            )),
+            None => Err(miette!(
+                severity = Severity::Error,
+                labels = vec![LabeledSpan::at_offset(
+                    ts.lexer().bytes().len() - 1,
+                    "expected: behavior"
+                )],
+                "Missing expected token"
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Layer {
+    pub name: Spanned<String>,
+    pub config: Option<Spanned<Config>>,
+    pub indicator: Option<Spanned<String>>,
+    pub statements: Box<[Spanned<Statement>]>,
+}
+impl Parse for Layer {
+    fn parse(ts: &mut TokenStream<'_>) -> miette::Result<Self> {
+        expect_tokens(ts, [TokenType::Layer])?;
+        let name = String::parse_spanned(ts)?;
+        let items: Box<[Spanned<LayerItem>]> = parse_block(ts)?;
+
+        let mut config: Option<Spanned<Config>> = None;
+        let mut indicator: Option<Spanned<String>> = None;
+        let mut rest = Vec::new();
+        for item in items {
+            match item.value {
+                LayerItem::Config(_) if let Some(original) = &config => {
+                    return Err(miette!(
+                        severity = Severity::Error,
+                        labels = vec![
+                            LabeledSpan::new(
+                                Some("original config block".to_string()),
+                                original.span.start(),
+                                original.span.len()
+                            ),
+                            LabeledSpan::new(
+                                Some("duplicate config block".to_string()),
+                                item.span.start(),
+                                item.span.len()
+                            ),
+                        ],
+                        help = "Merge the entries into one config block",
+                        "A layer can only have one config block"
+                    ));
+                }
+                LayerItem::Config(c) => config = Some(Spanned::new(c, item.span)),
+                LayerItem::Indicator(text) if let Some(original) = &indicator => {
+                    return Err(miette!(
+                        severity = Severity::Error,
+                        labels = vec![
+                            LabeledSpan::new(
+                                Some("original indicator".to_string()),
+                                original.span.start(),
+                                original.span.len()
+                            ),
+                            LabeledSpan::new(
+                                Some("duplicate indicator".to_string()),
+                                text.span.start(),
+                                text.span.len()
+                            ),
+                        ],
+                        "A layer can only have one indicator"
+                    ));
+                }
+                LayerItem::Indicator(text) => indicator = Some(text),
+                _ => rest.push(item),
+            }
+        }
+
+        let mut statements = Vec::new();
+        flatten_layer_items(rest.into_boxed_slice(), None, None, &[], &mut statements)?;
+
+        Ok(Self {
+            name,
+            config,
+            indicator,
+            statements: statements.into_boxed_slice(),
+        })
+    }
+}
+
+impl Layer {
+    pub fn config_data(&self, profile_config: &ConfigData) -> ConfigData {
+        let mut data = profile_config.clone();
+        if let Some(config) = &self.config {
+            config.apply(&mut data);
+        }
+        data
+    }
+}
+
+enum LayerItem {
+    Statement(Statement),
+    Swap(Spanned<KeyRef>, Spanned<KeyRef>),
+    Grid(Vec<(KeyIdent, Spanned<Bind>)>),
+    Config(Config),
+    Indicator(Spanned<String>),
+    When {
+        app: Spanned<NameMatcher>,
+        items: Box<[Spanned<LayerItem>]>,
+    },
+    On {
+        platform: Spanned<TargetOs>,
+        items: Box<[Spanned<LayerItem>]>,
+    },
+    If {
+        conditions: Box<[Spanned<Condition>]>,
+        items: Box<[Spanned<LayerItem>]>,
+    },
+}
+impl Parse for LayerItem {
+    fn parse(ts: &mut TokenStream<'_>) -> miette::Result<Self> {
+        if next_match!(ts, TokenType::If) {
+            let conditions = parse_conditions(ts)?;
+            let items = parse_block(ts)?;
+            Ok(LayerItem::If { conditions, items })
+        } else if next_match!(ts, TokenType::When) {
+            expect_tokens(ts, [TokenType::When, TokenType::App, TokenType::LParen])?;
+            let app = NameMatcher::parse_spanned(ts)?;
+            expect_tokens(ts, [TokenType::RParen])?;
+            let items = parse_block(ts)?;
+            Ok(LayerItem::When { app, items })
+        } else if next_match!(ts, TokenType::On) {
+            expect_tokens(ts, [TokenType::On])?;
+            let platform = TargetOs::parse_spanned(ts)?;
+            let items = parse_block(ts)?;
+            Ok(LayerItem::On { platform, items })
+        } else if next_match!(ts, TokenType::Config) {
+            Ok(LayerItem::Config(Config::parse(ts)?))
+        } else if next_match!(ts, TokenType::Ident, TokenType::Equals)
+            && ts.peek().is_some_and(|t| t.bytes() == "indicator")
+        {
+            expect_tokens(ts, [TokenType::Ident, TokenType::Equals])?;
+            Ok(LayerItem::Indicator(String::parse_spanned(ts)?))
+        } else if next_match!(ts, TokenType::Grid) {
+            parse_grid(ts)
+        } else if next_match!(ts, TokenType::Swap) {
+            expect_tokens(ts, [TokenType::Swap, TokenType::LParen])?;
+            let a = KeyRef::parse_spanned(ts)?;
+            expect_tokens(ts, [TokenType::Comma])?;
+            let b = KeyRef::parse_spanned(ts)?;
+            expect_tokens(ts, [TokenType::RParen])?;
+            Ok(LayerItem::Swap(a, b))
+        } else {
+            let lhs = Trigger::parse_spanned(ts)?;
+            if !next_match!(ts, TokenType::SwapArrow) {
+                return Ok(LayerItem::Statement(Statement::parse_with_lhs(ts, lhs)?));
+            }
+            let Trigger::Key(Spanned {
+                value: Key::Unspecified(a),
+                ..
+            }) = lhs.value
+            else {
                 return Err(miette!(
                     severity = Severity::Error,
                     labels = vec![LabeledSpan::new(
-                        Some(format!(
-                            "expected: behavior, found: {}",
-                            next_token.unwrap().kind()
-                        )),
-                        next_token.unwrap().start(),
-                        next_token.unwrap().bytes().len()
+                        Some("not a single key".to_string()),
+                        lhs.span.start(),
+                        lhs.span.len()
                     )],
-                    "Unexpected token found"
-                ))
+                    help = "Only keys can be swapped, without `^` or `_`",
+                    "Invalid left side of `<->`"
+                ));
+            };
+            expect_tokens(ts, [TokenType::SwapArrow])?;
+            let b = KeyRef::parse_spanned(ts)?;
+            Ok(LayerItem::Swap(a, b))
+        }
+    }
+}
+
+fn flatten_layer_items(
+    items: Box<[Spanned<LayerItem>]>,
+    app: Option<&Spanned<NameMatcher>>,
+    platform: Option<&Spanned<TargetOs>>,
+    conditions: &[Spanned<Condition>],
+    out: &mut Vec<Spanned<Statement>>,
+) -> miette::Result<()> {
+    let scope = |mut statement: Statement| {
+        statement.app = app.cloned();
+        statement.platform = platform.cloned();
+        statement.conditions = conditions
+            .iter()
+            .cloned()
+            .chain(statement.conditions)
+            .collect();
+        statement
+    };
+    for item in items {
+        match item.value {
+            LayerItem::Statement(statement) => {
+                out.push(Spanned::new(scope(statement), item.span));
             }
-            None => {
+            LayerItem::Swap(a, b) if a.value == b.value => {
                 return Err(miette!(
                     severity = Severity::Error,
-                    labels = vec![LabeledSpan::at_offset(
-                        ts.lexer().bytes().len() - 1,
-                        "expected: behavior"
+                    labels = vec![LabeledSpan::new(
+                        Some(format!("{} swapped with itself", a.value)),
+                        item.span.start(),
+                        item.span.len()
                     )],
-                    "Missing expected token"
-                ))
+                    "A key cannot be swapped with itself"
+                ));
+            }
+            LayerItem::Swap(a, b) => {
+                // Both halves share the span of the swap, so conflicts point at it
+                for (from, to) in [(a, b), (b, a)] {
+                    let statement = Statement::key_to_key(from, to);
+                    out.push(Spanned::new(scope(statement), item.span));
+                }
+            }
+            LayerItem::Grid(cells) => {
+                // Each cell is its own statement, so conflicts point at the cell
+                for (key, bind) in cells {
+                    let span = bind.span;
+                    let key = Spanned::new(KeyRef::Pos(key), span);
+                    let statement = Statement::key_to_bind(key, bind);
+                    out.push(Spanned::new(scope(statement), span));
+                }
+            }
+            LayerItem::When { .. } if app.is_some() => {
+                return Err(nested_block_error("when", item.span));
+            }
+            LayerItem::When { app, items } => {
+                flatten_layer_items(items, Some(&app), platform, conditions, out)?
+            }
+            LayerItem::On { .. } if platform.is_some() => {
+                return Err(nested_block_error("on", item.span));
+            }
+            LayerItem::On { platform, items } => {
+                flatten_layer_items(items, app, Some(&platform), conditions, out)?
+            }
+            // Conditions of nested `if` blocks add up
+            LayerItem::If {
+                conditions: inner,
+                items,
+            } => {
+                let conditions: Vec<_> = conditions.iter().chain(inner.iter()).cloned().collect();
+                flatten_layer_items(items, app, platform, &conditions, out)?
+            }
+            LayerItem::Config(_) => {
+                return Err(miette!(
+                    severity = Severity::Error,
+                    labels = vec![LabeledSpan::new(
+                        Some("nested config block".to_string()),
+                        item.span.start(),
+                        item.span.len()
+                    )],
+                    help = "Move the config block directly into the layer",
+                    "Layer config blocks cannot be placed inside `when`, `on` or `if` blocks"
+                ));
+            }
+            LayerItem::Indicator(_) => {
+                return Err(miette!(
+                    severity = Severity::Error,
+                    labels = vec![LabeledSpan::new(
+                        Some("nested indicator".to_string()),
+                        item.span.start(),
+                        item.span.len()
+                    )],
+                    help = "Move the indicator directly into the layer",
+                    "Layer indicators cannot be placed inside `when`, `on` or `if` blocks"
+                ));
             }
         }
     }
+    Ok(())
 }
 
-#[derive(Debug, Clone)]
-pub struct Layer {
-    pub name: Spanned<String>,
-    pub statements: Box<[Spanned<Statement>]>,
+/// Parses `grid "layout" { ... }` and pairs every cell with the key at its position. `_` cells
+/// and cells that bind the key at their own position are left out.
+fn parse_grid(ts: &mut TokenStream<'_>) -> miette::Result<LayerItem> {
+    expect_tokens(ts, [TokenType::Grid])?;
+    let name = String::parse_spanned(ts)?;
+    let Some(layout) = PhysicalLayout::find(&name) else {
+        return Err(miette!(
+            severity = Severity::Error,
+            labels = vec![LabeledSpan::new(
+                Some("unknown layout".to_string()),
+                name.span.start(),
+                name.span.len()
+            )],
+            help = format!(
+                "Available layouts are {}",
+                PhysicalLayout::names().join(", ")
+            ),
+            "Unknown physical layout '{}'",
+            name.value
+        ));
+    };
+
+    let rows: Box<[Spanned<GridRow>]> = parse_block(ts)?;
+    if rows.len() != layout.rows.len() {
+        return Err(miette!(
+            severity = Severity::Error,
+            labels = vec![LabeledSpan::new(
+                Some(format!(
+                    "expected {} rows, found {}",
+                    layout.rows.len(),
+                    rows.len()
+                )),
+                name.span.start(),
+                name.span.len()
+            )],
+            "Grid does not match the {} layout",
+            layout.name
+        ));
+    }
+
+    let mut cells = Vec::new();
+    for (row, keys) in rows.into_iter().zip(layout.rows) {
+        if row.0.len() != keys.len() {
+            return Err(miette!(
+                severity = Severity::Error,
+                labels = vec![LabeledSpan::new(
+                    Some(format!(
+                        "expected {} keys, found {}",
+                        keys.len(),
+                        row.0.len()
+                    )),
+                    row.span.start(),
+                    row.span.len()
+                )],
+                "Grid row does not match the {} layout",
+                layout.name
+            ));
+        }
+
+        for (cell, key) in row.value.0.into_iter().zip(keys.iter()) {
+            let Some(bind) = cell else {
+                continue;
+            };
+            if let Bind::Key(bind_key) = &bind.value
+                && bind_key.value.is_basic_key() == Some(KeyRef::Pos(*key))
+            {
+                continue;
+            }
+            cells.push((*key, bind));
+        }
+    }
+
+    Ok(LayerItem::Grid(cells))
 }
-impl Parse for Layer {
+
+/// The cells of one row of a `grid` block separated by spaces, `None` for `_`
+struct GridRow(Vec<Option<Spanned<Bind>>>);
+impl Parse for GridRow {
+    fn parse(ts: &mut TokenStream<'_>) -> miette::Result<Self> {
+        let mut cells = Vec::new();
+        let mut previous_end = None;
+        while !next_match!(ts, TokenType::Newline | TokenType::RCurly | TokenType::Eof) {
+            let next = ts.peek().unwrap();
+            if previous_end == Some(next.start()) {
+                return Err(miette!(
+                    severity = Severity::Error,
+                    labels = vec![LabeledSpan::new(
+                        Some("no space before this cell".to_string()),
+                        next.start(),
+                        next.bytes().len()
+                    )],
+                    help = "Separate the cells with spaces, or quote the key if it is a symbol",
+                    "Grid cells must be separated by spaces"
+                ));
+            }
+            if next_match!(ts, TokenType::Underscore) {
+                let [underscore] = expect_tokens(ts, [TokenType::Underscore])?;
+                previous_end = Some(underscore.start() + underscore.bytes().len());
+                cells.push(None);
+            } else {
+                let bind = Bind::parse_spanned(ts)?;
+                previous_end = Some(bind.span.end());
+                cells.push(Some(bind));
+            }
+        }
+        Ok(GridRow(cells))
+    }
+}
+
+/// A double quoted character that is not a key name, like `"!"`, is the same bind as `'!'`
+fn symbol_string(token: &Token<'_>) -> Option<char> {
+    if token.kind() != TokenType::StringLit {
+        return None;
+    }
+    let value = token.string_value();
+    let mut chars = value.chars();
+    let (Some(c), None) = (chars.next(), chars.next()) else {
+        return None;
+    };
+    value.parse::<KeyIdent>().is_err().then_some(c)
+}
+
+fn nested_block_error(kind: &str, span: Span) -> miette::Report {
+    miette!(
+        severity = Severity::Error,
+        labels = vec![LabeledSpan::new(
+            Some(format!("nested {kind} block")),
+            span.start(),
+            span.len()
+        )],
+        "`{}` blocks cannot be nested inside each other",
+        kind
+    )
+}
+
+impl Parse for LogicalLayout {
     fn parse(ts: &mut TokenStream<'_>) -> miette::Result<Self> {
-        expect_tokens(ts, [TokenType::Layer])?;
         let name = String::parse_spanned(ts)?;
-        expect_tokens(ts, [TokenType::LCurly, TokenType::Newline])?;
-        let statements = parse_sequence_trailing(ts, TokenType::Newline, TokenType::RCurly)?;
-        expect_tokens(ts, [TokenType::RCurly])?;
-        return Ok(Self { name, statements });
+        name.parse().map_err(|_| {
+            miette!(
+                severity = Severity::Error,
+                labels = vec![LabeledSpan::new(
+                    Some("unknown layout".to_string()),
+                    name.span.start(),
+                    name.span.len()
+                )],
+                help = "Available layouts are qwerty, azerty, qwertz, dvorak and colemak",
+                "Unknown keyboard layout '{}'",
+                name.value
+            )
+        })
+    }
+}
+
+impl Parse for TargetOs {
+    fn parse(ts: &mut TokenStream<'_>) -> miette::Result<Self> {
+        let [ident_token] = expect_tokens(ts, [TokenType::Ident])?;
+        ident_token.bytes().parse().map_err(|_| {
+            miette!(
+                severity = Severity::Error,
+                labels = vec![LabeledSpan::new(
+                    Some(format!(
+                        "expected: linux, macos or windows, found: {}",
+                        ident_token.bytes()
+                    )),
+                    ident_token.start(),
+                    ident_token.bytes().len()
+                )],
+                "Unknown platform"
+            )
+        })
+    }
+}
+
+impl Profile {
+    pub fn for_target(mut self, target: TargetOs) -> Self {
+        self.config.value = self.config.value.for_target(target);
+        for layer in self.layers.iter_mut() {
+            layer.config = layer
+                .config
+                .take()
+                .map(|c| Spanned::new(c.value.for_target(target), c.span));
+            layer.statements = std::mem::take(&mut layer.statements)
+                .into_iter()
+                .filter(|s| s.platform.as_ref().is_none_or(|p| p.value == target))
+                .collect();
+        }
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum NameMatcher {
+    Exact(String),
+    Glob(String),
+    Regex(String),
+}
+impl Parse for NameMatcher {
+    fn parse(ts: &mut TokenStream<'_>) -> miette::Result<Self> {
+        if next_match!(ts, TokenType::Tilde) {
+            expect_tokens(ts, [TokenType::Tilde])?;
+            Ok(NameMatcher::Regex(String::parse(ts)?))
+        } else if next_match!(ts, TokenType::Ident, TokenType::LParen) {
+            let [ident_token, _] = expect_tokens(ts, [TokenType::Ident, TokenType::LParen])?;
+            if ident_token.bytes() != "glob" {
+                return Err(miette!(
+                    severity = Severity::Error,
+                    labels = vec![LabeledSpan::new(
+                        Some(format!("expected: glob, found: {}", ident_token.bytes())),
+                        ident_token.start(),
+                        ident_token.bytes().len()
+                    )],
+                    help = "Use \"name\", glob(\"pattern\") or ~\"regex\"",
+                    "Unexpected name matcher"
+                ));
+            }
+            let pattern = String::parse(ts)?;
+            expect_tokens(ts, [TokenType::RParen])?;
+            Ok(NameMatcher::Glob(pattern))
+        } else {
+            Ok(NameMatcher::Exact(String::parse(ts)?))
+        }
     }
 }
 
@@ -221,57 +932,411 @@ impl Parse for Layer {
 pub struct Statement {
     pub lhs: Spanned<Trigger>,
     pub rhs: Box<[Spanned<Bind>]>,
+    pub app: Option<Spanned<NameMatcher>>,
+    pub platform: Option<Spanned<TargetOs>>,
+    pub conditions: Box<[Spanned<Condition>]>,
 }
 impl Parse for Statement {
     fn parse(ts: &mut TokenStream<'_>) -> miette::Result<Self> {
         let lhs = Trigger::parse_spanned(ts)?;
+        Self::parse_with_lhs(ts, lhs)
+    }
+}
+impl Statement {
+    fn parse_with_lhs(ts: &mut TokenStream<'_>, lhs: Spanned<Trigger>) -> miette::Result<Self> {
+        let conditions = if next_match!(ts, TokenType::If) {
+            parse_conditions(ts)?
+        } else {
+            Box::new([])
+        };
         expect_tokens(ts, [TokenType::Equals])?;
+        skip_newline(ts)?;
         let rhs = if next_match!(ts, TokenType::LSquare) {
             parse_square_bracket_list(ts)?
         } else {
             vec![Bind::parse_spanned(ts)?].into_boxed_slice()
         };
 
-        Ok(Self { lhs, rhs })
+        Ok(Self {
+            lhs,
+            rhs,
+            app: None,
+            platform: None,
+            conditions,
+        })
+    }
+}
+
+fn parse_conditions(ts: &mut TokenStream<'_>) -> miette::Result<Box<[Spanned<Condition>]>> {
+    expect_tokens(ts, [TokenType::If])?;
+    let mut conditions = vec![Condition::parse_spanned(ts)?];
+    while next_match!(ts, TokenType::AndAnd) {
+        expect_tokens(ts, [TokenType::AndAnd])?;
+        conditions.push(Condition::parse_spanned(ts)?);
+    }
+    Ok(conditions.into_boxed_slice())
+}
+
+#[derive(Debug, Clone)]
+pub enum Condition {
+    Held(Spanned<KeyRef>),
+    NotHeld(Spanned<KeyRef>),
+    ToggleOn(Spanned<String>),
+    ToggleOff(Spanned<String>),
+}
+
+impl Condition {
+    pub fn contradicts(&self, other: &Condition) -> bool {
+        match (self, other) {
+            (Condition::Held(a), Condition::NotHeld(b))
+            | (Condition::NotHeld(a), Condition::Held(b)) => a.value == b.value,
+            (Condition::ToggleOn(a), Condition::ToggleOff(b))
+            | (Condition::ToggleOff(a), Condition::ToggleOn(b)) => a.value == b.value,
+            _ => false,
+        }
+    }
+}
+
+impl Parse for Condition {
+    fn parse(ts: &mut TokenStream<'_>) -> miette::Result<Self> {
+        let negated = next_match!(ts, TokenType::Bang);
+        if negated {
+            expect_tokens(ts, [TokenType::Bang])?;
+        }
+        if !next_match!(ts, TokenType::Held) {
+            let name = parse_toggle_name(ts)?;
+            return Ok(if negated {
+                Condition::ToggleOff(name)
+            } else {
+                Condition::ToggleOn(name)
+            });
+        }
+
+        expect_tokens(ts, [TokenType::Held, TokenType::LParen])?;
+        let key = KeyRef::parse_spanned(ts)?;
+        expect_tokens(ts, [TokenType::RParen])?;
+        Ok(if negated {
+            Condition::NotHeld(key)
+        } else {
+            Condition::Held(key)
+        })
+    }
+}
+
+impl Statement {
+    fn key_to_key(from: Spanned<KeyRef>, to: Spanned<KeyRef>) -> Self {
+        let bind = Bind::Key(Spanned::new(Key::Unspecified(to), to.span));
+        Self::key_to_bind(from, Spanned::new(bind, to.span))
+    }
+
+    fn key_to_bind(key: Spanned<KeyRef>, bind: Spanned<Bind>) -> Self {
+        Self {
+            lhs: Spanned::new(
+                Trigger::Key(Spanned::new(Key::Unspecified(key), key.span)),
+                key.span,
+            ),
+            rhs: Box::new([bind]),
+            app: None,
+            platform: None,
+            conditions: Box::new([]),
+        }
+    }
+}
+
+impl Statement {
+    /// Replaces the character keys of the statement with the keys that type them on the layout.
+    /// Character binds that need shift or AltGr become [`Bind::Symbol`].
+    pub fn resolve_chars(&mut self, layout: LogicalLayout) -> miette::Result<()> {
+        match &mut self.lhs.value {
+            Trigger::Key(key) => key.resolve_chars(layout)?,
+            Trigger::Tap(key, ..) | Trigger::Hold(key, ..) => {
+                resolve_char(&mut key.value, key.span, layout)?
+            }
+            Trigger::Chord(keys, ..) => {
+                for key in keys.iter_mut() {
+                    resolve_char(&mut key.value, key.span, layout)?;
+                }
+            }
+            Trigger::Sequence(steps, ..) => {
+                for step in steps.iter_mut() {
+                    if let Step::Key(key) = &mut step.value {
+                        resolve_char(key, step.span, layout)?;
+                    }
+                }
+            }
+            Trigger::Combo(steps, ..) => {
+                for step in steps.iter_mut() {
+                    if let Step::Key(key) = &mut step.value {
+                        key.resolve_chars(layout)?;
+                    }
+                }
+            }
+            Trigger::KeySet(_)
+            | Trigger::AppFocused(_)
+            | Trigger::AppUnfocused(_)
+            | Trigger::WindowTitle(_) => (),
+        }
+
+        for condition in self.conditions.iter_mut() {
+            if let Condition::Held(key) | Condition::NotHeld(key) = &mut condition.value {
+                resolve_char(&mut key.value, key.span, layout)?;
+            }
+        }
+
+        for bind in self.rhs.iter_mut() {
+            let Bind::Key(key) = &mut bind.value else {
+                continue;
+            };
+            let Key::Unspecified(Spanned {
+                value: KeyRef::Char(c),
+                span,
+            }) = key.value
+            else {
+                key.resolve_chars(layout)?;
+                continue;
+            };
+            bind.value = match layout.find_char(c) {
+                Some((None, key)) => Bind::Key(Spanned::new(
+                    Key::Unspecified(Spanned::new(KeyRef::Pos(key), span)),
+                    span,
+                )),
+                Some((Some(modifier), key)) => Bind::Symbol { modifier, key },
+                None => {
+                    return Err(miette!(
+                        severity = Severity::Error,
+                        labels = vec![LabeledSpan::new(
+                            Some(format!("'{c}' is not on the {layout} layout")),
+                            span.start(),
+                            span.len()
+                        )],
+                        help = format!(
+                            "Letters, digits and these symbols can be typed on {layout}: {}\n\
+                             Use unicode(\"...\") for other characters",
+                            layout.symbols().into_iter().join(" ")
+                        ),
+                        "Character cannot be typed"
+                    ));
+                }
+            };
+        }
+        Ok(())
+    }
+}
+
+impl ConfigData {
+    pub fn resolve_chars(&mut self) -> miette::Result<()> {
+        for key in self.caps_word_keys.iter_mut() {
+            resolve_char(&mut key.value, key.span, self.keyboard_layout)?;
+        }
+        Ok(())
+    }
+}
+
+impl Key {
+    fn resolve_chars(&mut self, layout: LogicalLayout) -> miette::Result<()> {
+        match self {
+            Key::Unspecified(key) | Key::Down(key) | Key::Up(key) => {
+                resolve_char(&mut key.value, key.span, layout)
+            }
+        }
+    }
+}
+
+pub(crate) fn resolve_char(
+    key: &mut KeyRef,
+    span: Span,
+    layout: LogicalLayout,
+) -> miette::Result<()> {
+    let KeyRef::Char(c) = *key else {
+        return Ok(());
+    };
+    let resolved = layout.key_for_char(c).ok_or_else(|| {
+        miette!(
+            severity = Severity::Error,
+            labels = vec![LabeledSpan::new(
+                Some(format!("no key types '{c}' on {layout}")),
+                span.start(),
+                span.len()
+            )],
+            help = "Character keys must be typed by a single key without modifiers, use the key \
+                    name or pos(...) for the key at a position",
+            "Character not found in the keyboard layout"
+        )
+    })?;
+    *key = KeyRef::Pos(resolved);
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Step<K> {
+    Key(K),
+    Wait(Spanned<Duration>),
+    Within(Spanned<Duration>),
+}
+
+impl<K: Parse> Parse for Step<K> {
+    fn parse(ts: &mut TokenStream<'_>) -> miette::Result<Self> {
+        if next_match!(ts, TokenType::Wait, TokenType::LParen) {
+            expect_tokens(ts, [TokenType::Wait, TokenType::LParen])?;
+            let duration = Duration::parse_spanned(ts)?;
+            expect_tokens(ts, [TokenType::RParen])?;
+            return Ok(Step::Wait(duration));
+        }
+        if next_match!(ts, TokenType::Within, TokenType::LParen) {
+            expect_tokens(ts, [TokenType::Within, TokenType::LParen])?;
+            let duration = Duration::parse_spanned(ts)?;
+            expect_tokens(ts, [TokenType::RParen])?;
+            return Ok(Step::Within(duration));
+        }
+        Ok(Step::Key(K::parse(ts)?))
+    }
+}
+
+fn key_set_by_name(name: &str) -> Option<&'static [KeyIdent]> {
+    match name {
+        "letters" => Some(&KeyIdent::LETTERS),
+        "digits" => Some(&KeyIdent::DIGITS),
+        "fkeys" => Some(&KeyIdent::FUNCTION_KEYS),
+        _ => None,
+    }
+}
+
+fn parse_key_set(ts: &mut TokenStream<'_>) -> miette::Result<Vec<KeyIdent>> {
+    let mut keys = parse_key_set_term(ts)?;
+    while next_match!(ts, TokenType::Minus) {
+        expect_tokens(ts, [TokenType::Minus])?;
+        let removed = parse_key_set_term(ts)?;
+        keys.retain(|key| !removed.contains(key));
+    }
+    Ok(keys)
+}
+
+fn parse_key_set_term(ts: &mut TokenStream<'_>) -> miette::Result<Vec<KeyIdent>> {
+    if next_match!(ts, TokenType::Star) {
+        expect_tokens(ts, [TokenType::Star])?;
+        return Ok(KeyIdent::KEYBOARD.to_vec());
+    }
+    if next_match!(ts, TokenType::LSquare) {
+        let ranges: Box<[Spanned<KeyRange>]> = parse_square_bracket_list(ts)?;
+        let mut keys = Vec::new();
+        for key in ranges.iter().flat_map(|range| range.0.iter()) {
+            if !keys.contains(key) {
+                keys.push(*key);
+            }
+        }
+        return Ok(keys);
+    }
+
+    let [ident_token] = expect_tokens(ts, [TokenType::Ident])?;
+    key_set_by_name(ident_token.bytes())
+        .map(|keys| keys.to_vec())
+        .ok_or_else(|| {
+            miette!(
+                severity = Severity::Error,
+                labels = vec![LabeledSpan::new(
+                    Some(format!("expected: key set, found: {}", ident_token.bytes())),
+                    ident_token.start(),
+                    ident_token.bytes().len()
+                )],
+                help = "Key sets are `*`, `letters`, `digits`, `fkeys` or a list like `[a-f, x]`",
+                "Unknown key set"
+            )
+        })
+}
+
+struct KeyRange(Vec<KeyIdent>);
+impl Parse for KeyRange {
+    fn parse(ts: &mut TokenStream<'_>) -> miette::Result<Self> {
+        let first = KeyIdent::parse_spanned(ts)?;
+        if !next_match!(ts, TokenType::Minus) {
+            return Ok(KeyRange(vec![first.value]));
+        }
+        expect_tokens(ts, [TokenType::Minus])?;
+        let last = KeyIdent::parse_spanned(ts)?;
+
+        // Ranges only make sense within a group that has an order
+        let range = [
+            &KeyIdent::LETTERS[..],
+            &KeyIdent::DIGITS,
+            &KeyIdent::FUNCTION_KEYS,
+        ]
+        .into_iter()
+        .find_map(|group| {
+            let start = group.iter().position(|k| *k == first.value)?;
+            let end = group.iter().position(|k| *k == last.value)?;
+            (start <= end).then(|| group[start..=end].to_vec())
+        });
+
+        range.map(KeyRange).ok_or_else(|| {
+            let span = first.span.join(last.span);
+            miette!(
+                severity = Severity::Error,
+                labels = vec![LabeledSpan::new(
+                    Some("invalid range".to_string()),
+                    span.start(),
+                    span.len()
+                )],
+                help = "Ranges go from a lower to a higher letter, digit or function key, like \
+                        `a-z`, `0-9` or `f1-f12`",
+                "Invalid key range"
+            )
+        })
     }
 }
 
 #[derive(Debug, Clone)]
 pub enum Trigger {
     Key(Spanned<Key>),
+    KeySet(Box<[KeyIdent]>),
     AppFocused(Spanned<String>),
+    AppUnfocused(Spanned<String>),
+    WindowTitle(Spanned<NameMatcher>),
     Chord(
-        Box<[Spanned<KeyIdent>]>,
+        Box<[Spanned<KeyRef>]>,
         Option<Spanned<Behavior>>,
         Option<Spanned<usize>>,
+        Option<Spanned<Duration>>,
     ),
     Sequence(
-        Box<[Spanned<KeyIdent>]>,
+        Box<[Spanned<Step<KeyRef>>]>,
         Option<Spanned<Behavior>>,
         Option<Spanned<usize>>,
+        Option<Spanned<Duration>>,
     ),
     Tap(
-        Spanned<KeyIdent>,
+        Spanned<KeyRef>,
         Option<Spanned<Behavior>>,
         Option<Spanned<usize>>,
+        Option<Spanned<Duration>>,
     ),
     Hold(
-        Spanned<KeyIdent>,
+        Spanned<KeyRef>,
         Option<Spanned<Behavior>>,
         Option<Spanned<usize>>,
+        Option<Spanned<Duration>>,
     ),
     Combo(
-        Box<[Spanned<Key>]>,
+        Box<[Spanned<Step<Key>>]>,
         Option<Spanned<Behavior>>,
         Option<Spanned<usize>>,
+        Option<Spanned<Duration>>,
     ),
 }
 impl Parse for Trigger {
     fn parse(ts: &mut TokenStream<'_>) -> miette::Result<Self> {
+        let named_key_set = ts
+            .peek()
+            .is_some_and(|t| t.kind() == TokenType::Ident && key_set_by_name(t.bytes()).is_some());
+        if named_key_set || next_match!(ts, TokenType::LSquare | TokenType::Star) {
+            return Ok(Trigger::KeySet(parse_key_set(ts)?.into_boxed_slice()));
+        }
+
         match ts.peek_type() {
             Some(TokenType::Ident)
             | Some(TokenType::StringLit)
             | Some(TokenType::IntLit)
+            | Some(TokenType::CharLit)
+            | Some(TokenType::ParamRef)
             | Some(TokenType::Caret)
             | Some(TokenType::Underscore) => Ok(Trigger::Key(Key::parse_spanned(ts)?)),
             Some(TokenType::AppFocused) => {
@@ -280,37 +1345,49 @@ impl Parse for Trigger {
                 expect_tokens(ts, [TokenType::RParen])?;
                 Ok(Trigger::AppFocused(app_name))
             }
+            Some(TokenType::AppUnfocused) => {
+                expect_tokens(ts, [TokenType::AppUnfocused, TokenType::LParen])?;
+                let app_name = String::parse_spanned(ts)?;
+                expect_tokens(ts, [TokenType::RParen])?;
+                Ok(Trigger::AppUnfocused(app_name))
+            }
+            Some(TokenType::WindowTitle) => {
+                expect_tokens(ts, [TokenType::WindowTitle, TokenType::LParen])?;
+                let title = NameMatcher::parse_spanned(ts)?;
+                expect_tokens(ts, [TokenType::RParen])?;
+                Ok(Trigger::WindowTitle(title))
+            }
             Some(TokenType::Chord) => {
                 expect_tokens(ts, [TokenType::Chord, TokenType::LParen])?;
                 let keys = parse_square_bracket_list(ts)?;
-                let (behavior, timeout) = parse_optional_trigger_args(ts)?;
-                Ok(Trigger::Chord(keys, behavior, timeout))
+                let (behavior, timeout, prior_idle) = parse_optional_trigger_args(ts)?;
+                Ok(Trigger::Chord(keys, behavior, timeout, prior_idle))
             }
             Some(TokenType::Sequence) => {
                 expect_tokens(ts, [TokenType::Sequence, TokenType::LParen])?;
                 let keys = parse_square_bracket_list(ts)?;
-                let (behavior, timeout) = parse_optional_trigger_args(ts)?;
-                Ok(Trigger::Sequence(keys, behavior, timeout))
+                let (behavior, timeout, prior_idle) = parse_optional_trigger_args(ts)?;
+                Ok(Trigger::Sequence(keys, behavior, timeout, prior_idle))
             }
 
             Some(TokenType::Tap) => {
                 expect_tokens(ts, [TokenType::Tap, TokenType::LParen])?;
-                let key = KeyIdent::parse_spanned(ts)?;
-                let (behavior, timeout) = parse_optional_trigger_args(ts)?;
-                Ok(Trigger::Tap(key, behavior, timeout))
+                let key = KeyRef::parse_spanned(ts)?;
+                let (behavior, timeout, prior_idle) = parse_optional_trigger_args(ts)?;
+                Ok(Trigger::Tap(key, behavior, timeout, prior_idle))
             }
 
             Some(TokenType::Hold) => {
                 expect_tokens(ts, [TokenType::Hold, TokenType::LParen])?;
-                let key = KeyIdent::parse_spanned(ts)?;
-                let (behavior, timeout) = parse_optional_trigger_args(ts)?;
-                Ok(Trigger::Hold(key, behavior, timeout))
+                let key = KeyRef::parse_spanned(ts)?;
+                let (behavior, timeout, prior_idle) = parse_optional_trigger_args(ts)?;
+                Ok(Trigger::Hold(key, behavior, timeout, prior_idle))
             }
             Some(TokenType::Combo) => {
                 expect_tokens(ts, [TokenType::Combo, TokenType::LParen])?;
                 let keys = parse_square_bracket_list(ts)?;
-                let (behavior, timeout) = parse_optional_trigger_args(ts)?;
-                Ok(Trigger::Combo(keys, behavior, timeout))
+                let (behavior, timeout, prior_idle) = parse_optional_trigger_args(ts)?;
+                Ok(Trigger::Combo(keys, behavior, timeout, prior_idle))
             }
             Some(_) => {
                 let unexpected_token = ts.peek().unwrap();
@@ -348,15 +1425,42 @@ pub enum Bind {
         interpreter: Spanned<String>,
         script: Spanned<String>,
     },
+    Exec {
+        argv: Box<[Spanned<String>]>,
+        options: Box<[Spanned<ExecOption>]>,
+    },
     OpenApp(Spanned<String>),
+    CapsWord,
+    MouseMove {
+        dx: Spanned<i32>,
+        dy: Spanned<i32>,
+    },
+    Unicode(Spanned<String>),
+    Notify(Spanned<String>),
+    SetToggle(Spanned<String>, Spanned<bool>),
+    FlipToggle(Spanned<String>),
+    /// A character typed by holding the modifier while tapping the key, like shift and 1 for `!`.
+    /// Character binds that need a modifier on the keyboard layout are resolved to this.
+    Symbol {
+        modifier: KeyIdent,
+        key: KeyIdent,
+    },
 }
 
 impl Parse for Bind {
     fn parse(ts: &mut TokenStream<'_>) -> miette::Result<Self> {
+        if let Some(c) = ts.peek().and_then(symbol_string) {
+            let [str_token] = expect_tokens(ts, [TokenType::StringLit])?;
+            let span = str_token.loc();
+            let key = Spanned::new(KeyRef::Char(c), span);
+            return Ok(Bind::Key(Spanned::new(Key::Unspecified(key), span)));
+        }
         match ts.peek_type() {
-            Some(TokenType::Ident)
+            Some(TokenType::CharLit)
+            | Some(TokenType::Ident)
             | Some(TokenType::StringLit)
             | Some(TokenType::IntLit)
+            | Some(TokenType::ParamRef)
             | Some(TokenType::Caret)
             | Some(TokenType::Underscore) => Ok(Bind::Key(Key::parse_spanned(ts)?)),
             Some(TokenType::NoneKw) => {
@@ -369,6 +1473,22 @@ impl Parse for Bind {
                 expect_tokens(ts, [TokenType::RParen])?;
                 Ok(Bind::ChangeLayer(new_layer))
             }
+            Some(TokenType::Run)
+                if next_match!(ts, TokenType::Run, TokenType::LParen, TokenType::LSquare) =>
+            {
+                expect_tokens(ts, [TokenType::Run, TokenType::LParen])?;
+                let argv = parse_square_bracket_list(ts)?;
+                let mut options = Vec::new();
+                while !next_match!(ts, TokenType::RParen) {
+                    expect_tokens(ts, [TokenType::Comma])?;
+                    options.push(ExecOption::parse_spanned(ts)?);
+                }
+                expect_tokens(ts, [TokenType::RParen])?;
+                Ok(Bind::Exec {
+                    argv,
+                    options: options.into_boxed_slice(),
+                })
+            }
             Some(TokenType::Run) => {
                 expect_tokens(ts, [TokenType::Run, TokenType::LParen])?;
                 let interpreter = String::parse_spanned(ts)?;
@@ -386,6 +1506,44 @@ impl Parse for Bind {
                 expect_tokens(ts, [TokenType::RParen])?;
                 Ok(Bind::OpenApp(app_name))
             }
+            Some(TokenType::CapsWord) => {
+                expect_tokens(ts, [TokenType::CapsWord])?;
+                Ok(Bind::CapsWord)
+            }
+            Some(TokenType::Notify) => {
+                expect_tokens(ts, [TokenType::Notify, TokenType::LParen])?;
+                let message = String::parse_spanned(ts)?;
+                expect_tokens(ts, [TokenType::RParen])?;
+                Ok(Bind::Notify(message))
+            }
+            Some(TokenType::Set) => {
+                expect_tokens(ts, [TokenType::Set, TokenType::LParen])?;
+                let name = parse_toggle_name(ts)?;
+                expect_tokens(ts, [TokenType::Comma])?;
+                let value = bool::parse_spanned(ts)?;
+                expect_tokens(ts, [TokenType::RParen])?;
+                Ok(Bind::SetToggle(name, value))
+            }
+            Some(TokenType::Flip) => {
+                expect_tokens(ts, [TokenType::Flip, TokenType::LParen])?;
+                let name = parse_toggle_name(ts)?;
+                expect_tokens(ts, [TokenType::RParen])?;
+                Ok(Bind::FlipToggle(name))
+            }
+            Some(TokenType::MouseMove) => {
+                expect_tokens(ts, [TokenType::MouseMove, TokenType::LParen])?;
+                let dx = i32::parse_spanned(ts)?;
+                expect_tokens(ts, [TokenType::Comma])?;
+                let dy = i32::parse_spanned(ts)?;
+                expect_tokens(ts, [TokenType::RParen])?;
+                Ok(Bind::MouseMove { dx, dy })
+            }
+            Some(TokenType::Unicode) => {
+                expect_tokens(ts, [TokenType::Unicode, TokenType::LParen])?;
+                let text = String::parse_spanned(ts)?;
+                expect_tokens(ts, [TokenType::RParen])?;
+                Ok(Bind::Unicode(text))
+            }
             Some(_) => {
                 let unexpected_token = ts.peek().unwrap();
                 Err(miette!(
@@ -413,14 +1571,92 @@ impl Parse for Bind {
     }
 }
 
+#[derive(Debug, Clone)]
+pub enum ExecOption {
+    Cwd(Spanned<String>),
+    Env(Box<[Spanned<EnvVar>]>),
+    Timeout(Spanned<Duration>),
+    Detach(Spanned<bool>),
+}
+
+impl Parse for ExecOption {
+    fn parse(ts: &mut TokenStream<'_>) -> miette::Result<Self> {
+        let [ident_token, _] = expect_tokens(ts, [TokenType::Ident, TokenType::Equals])?;
+        Ok(match ident_token.bytes() {
+            "cwd" => ExecOption::Cwd(String::parse_spanned(ts)?),
+            "env" => {
+                expect_tokens(ts, [TokenType::LCurly])?;
+                let vars = parse_sequence(ts, TokenType::Comma, TokenType::RCurly)?;
+                expect_tokens(ts, [TokenType::RCurly])?;
+                ExecOption::Env(vars)
+            }
+            "timeout" => ExecOption::Timeout(Duration::parse_spanned(ts)?),
+            "detach" => ExecOption::Detach(bool::parse_spanned(ts)?),
+            _ => {
+                return Err(miette!(
+                    severity = Severity::Error,
+                    labels = vec![LabeledSpan::new(
+                        Some(format!("invalid run option {}", ident_token.bytes())),
+                        ident_token.start(),
+                        ident_token.bytes().len()
+                    )],
+                    help = "Valid options are cwd, env, timeout and detach",
+                    "Unexpected run option"
+                ))
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum TriggerOption {
+    RequirePriorIdle(Spanned<Duration>),
+}
+
+impl Parse for TriggerOption {
+    fn parse(ts: &mut TokenStream<'_>) -> miette::Result<Self> {
+        let [ident_token, _] = expect_tokens(ts, [TokenType::Ident, TokenType::Equals])?;
+        Ok(match ident_token.bytes() {
+            "require_prior_idle" => TriggerOption::RequirePriorIdle(Duration::parse_spanned(ts)?),
+            _ => {
+                return Err(miette!(
+                    severity = Severity::Error,
+                    labels = vec![LabeledSpan::new(
+                        Some(format!("invalid trigger option {}", ident_token.bytes())),
+                        ident_token.start(),
+                        ident_token.bytes().len()
+                    )],
+                    help = "Valid options are require_prior_idle",
+                    "Unexpected trigger option"
+                ))
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EnvVar {
+    pub name: Spanned<String>,
+    pub value: Spanned<String>,
+}
+
+impl Parse for EnvVar {
+    fn parse(ts: &mut TokenStream<'_>) -> miette::Result<Self> {
+        let name = String::parse_spanned(ts)?;
+        expect_tokens(ts, [TokenType::Colon])?;
+        let value = String::parse_spanned(ts)?;
+        Ok(Self { name, value })
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum Key {
-    Unspecified(Spanned<KeyIdent>),
-    Down(Spanned<KeyIdent>),
-    Up(Spanned<KeyIdent>),
+    Unspecified(Spanned<KeyRef>),
+    Down(Spanned<KeyRef>),
+    Up(Spanned<KeyRef>),
 }
 impl Key {
-    pub fn is_basic_key(self) -> Option<KeyIdent> {
+    pub fn is_basic_key(self) -> Option<KeyRef> {
         match self {
             Key::Unspecified(k) => Some(k.value),
             _ => None,
@@ -432,25 +1668,63 @@ impl Parse for Key {
     fn parse(ts: &mut TokenStream<'_>) -> miette::Result<Self> {
         Ok(if next_match!(ts, TokenType::Caret) {
             expect_tokens(ts, [TokenType::Caret])?;
-            Key::Up(KeyIdent::parse_spanned(ts)?)
+            Key::Up(KeyRef::parse_spanned(ts)?)
         } else if next_match!(ts, TokenType::Underscore) {
             expect_tokens(ts, [TokenType::Underscore])?;
-            Key::Down(KeyIdent::parse_spanned(ts)?)
+            Key::Down(KeyRef::parse_spanned(ts)?)
         } else {
-            Key::Unspecified(KeyIdent::parse_spanned(ts)?)
+            Key::Unspecified(KeyRef::parse_spanned(ts)?)
         })
     }
 }
 
+impl Parse for KeyRef {
+    fn parse(ts: &mut TokenStream<'_>) -> miette::Result<Self> {
+        if next_match!(ts, TokenType::ParamRef) {
+            return parse_param_ref(ts, &[ParamKind::Key, ParamKind::Number], KeyRef::parse);
+        }
+
+        // The key that types the character is only known once the layout of the layer is
+        if next_match!(ts, TokenType::CharLit) {
+            let [char_token] = expect_tokens(ts, [TokenType::CharLit])?;
+            return Ok(KeyRef::Char(char_token.char_value()));
+        }
+        Ok(KeyRef::Pos(KeyIdent::parse(ts)?))
+    }
+}
+
 impl Parse for KeyIdent {
     fn parse(ts: &mut TokenStream<'_>) -> miette::Result<Self> {
+        if next_match!(ts, TokenType::ParamRef) {
+            return parse_param_ref(ts, &[ParamKind::Key, ParamKind::Number], KeyIdent::parse);
+        }
+
+        if next_match!(ts, TokenType::CharLit) {
+            let [char_token] = expect_tokens(ts, [TokenType::CharLit])?;
+            return Err(miette!(
+                severity = Severity::Error,
+                labels = vec![LabeledSpan::new(
+                    Some("character key".to_string()),
+                    char_token.start(),
+                    char_token.bytes().len()
+                )],
+                help = "Key sets and pos(...) take the key at a position, use the key name instead",
+                "Character keys cannot be used here"
+            ));
+        }
+        // `pos(q)` is the key at the position of q on a QWERTY keyboard, same as a plain `q`
+        if next_match!(ts, TokenType::Ident, TokenType::LParen)
+            && ts.peek().is_some_and(|t| t.bytes() == "pos")
+        {
+            expect_tokens(ts, [TokenType::Ident, TokenType::LParen])?;
+            let key = KeyIdent::parse(ts)?;
+            expect_tokens(ts, [TokenType::RParen])?;
+            return Ok(key);
+        }
+
         let (token, result) = if next_match!(ts, TokenType::StringLit) {
             let [str_token] = expect_tokens(ts, [TokenType::StringLit])?;
-            let str_with_quotes = str_token.bytes();
-            (
-                str_token,
-                (&str_with_quotes[1..str_with_quotes.len() - 1]).parse(),
-            )
+            (str_token, str_token.string_value().parse())
         } else if next_match!(ts, TokenType::IntLit) {
             let [int_token] = expect_tokens(ts, [TokenType::IntLit])?;
             (int_token, int_token.bytes().parse())
@@ -460,13 +1734,29 @@ impl Parse for KeyIdent {
         };
 
         result.map_err(|_| {
+            let label = LabeledSpan::new(
+                Some(format!("expected: key, found: {}", token.bytes())),
+                token.start(),
+                token.bytes().len(),
+            );
+            // Double quotes only name keys, the key that types a character is single quoted
+            let value = (token.kind() == TokenType::StringLit).then(|| token.string_value());
+            let mut chars = value.iter().flat_map(|value| value.chars());
+            if let (Some(c), None) = (chars.next(), chars.next()) {
+                let char_key = match c {
+                    '\'' | '\\' => format!("'\\{c}'"),
+                    _ => format!("'{c}'"),
+                };
+                return miette!(
+                    severity = Severity::Error,
+                    labels = vec![label],
+                    help = format!("Use {char_key} for the key that types the character"),
+                    "Unexpected key found"
+                );
+            }
             miette!(
                 severity = Severity::Error,
-                labels = vec![LabeledSpan::new(
-                    Some(format!("expected: key, found: {}", token.bytes())),
-                    token.start(),
-                    token.bytes().len()
-                )],
+                labels = vec![label],
                 "Unexpected key found"
             )
         })
@@ -475,29 +1765,155 @@ impl Parse for KeyIdent {
 
 impl Parse for String {
     fn parse(ts: &mut TokenStream<'_>) -> miette::Result<Self> {
+        if next_match!(ts, TokenType::ParamRef) {
+            return parse_param_ref(ts, &[ParamKind::String], String::parse);
+        }
+
         let [str_token] = expect_tokens(ts, [TokenType::StringLit])?;
-        let str_with_quotes = str_token.bytes();
-        assert!(str_with_quotes.len() >= 2);
-        Ok(String::from(&str_with_quotes[1..str_with_quotes.len() - 1]))
+        Ok(str_token.string_value())
     }
 }
 
+/// Numbers are timeouts in milliseconds, so duration parameters can be used in their place
 impl Parse for usize {
     fn parse(ts: &mut TokenStream<'_>) -> miette::Result<Self> {
-        let [int_token] = expect_tokens(ts, [TokenType::IntLit])?;
-        match int_token.bytes().parse() {
-            Ok(i) => Ok(i),
-            _ => {
-                return Err(miette!(
+        if let Some(ref_token) = ts
+            .peek()
+            .copied()
+            .filter(|t| t.kind() == TokenType::ParamRef)
+        {
+            let duration = parse_param_ref(
+                ts,
+                &[ParamKind::Number, ParamKind::Duration],
+                Duration::parse,
+            )?;
+            return duration.as_millis().try_into().map_err(|_| {
+                miette!(
                     severity = Severity::Error,
                     labels = vec![LabeledSpan::new(
-                        Some("Invalid integer literal".to_string()),
-                        int_token.start(),
-                        int_token.bytes().len()
+                        Some("Invalid duration".to_string()),
+                        ref_token.start(),
+                        ref_token.bytes().len()
                     )],
-                    "Integer literal outside of range"
-                ))
-            }
+                    "Duration outside of range"
+                )
+            });
+        }
+
+        let [int_token] = expect_tokens(ts, [TokenType::IntLit])?;
+        match int_token.bytes().parse() {
+            Ok(i) => Ok(i),
+            _ => Err(miette!(
+                severity = Severity::Error,
+                labels = vec![LabeledSpan::new(
+                    Some("Invalid integer literal".to_string()),
+                    int_token.start(),
+                    int_token.bytes().len()
+                )],
+                "Integer literal outside of range"
+            )),
+        }
+    }
+}
+
+impl Parse for i32 {
+    fn parse(ts: &mut TokenStream<'_>) -> miette::Result<Self> {
+        let negative = next_match!(ts, TokenType::Minus);
+        if negative {
+            expect_tokens(ts, [TokenType::Minus])?;
+        }
+        let [int_token] = expect_tokens(ts, [TokenType::IntLit])?;
+        let digits = int_token.bytes();
+        let parsed = if negative {
+            format!("-{digits}").parse()
+        } else {
+            digits.parse()
+        };
+        parsed.map_err(|_| {
+            miette!(
+                severity = Severity::Error,
+                labels = vec![LabeledSpan::new(
+                    Some("Invalid integer literal".to_string()),
+                    int_token.start(),
+                    int_token.bytes().len()
+                )],
+                "Integer literal outside of range"
+            )
+        })
+    }
+}
+
+impl Parse for bool {
+    fn parse(ts: &mut TokenStream<'_>) -> miette::Result<Self> {
+        if next_match!(ts, TokenType::True) {
+            expect_tokens(ts, [TokenType::True])?;
+            Ok(true)
+        } else {
+            expect_tokens(ts, [TokenType::False])?;
+            Ok(false)
+        }
+    }
+}
+
+impl Parse for Duration {
+    fn parse(ts: &mut TokenStream<'_>) -> miette::Result<Self> {
+        if next_match!(ts, TokenType::ParamRef) {
+            return parse_param_ref(
+                ts,
+                &[ParamKind::Duration, ParamKind::Number],
+                Duration::parse,
+            );
         }
+        if !next_match!(ts, TokenType::DurationLit) {
+            return Ok(Duration::from_millis(u64::parse(ts)?));
+        }
+
+        let [duration_token] = expect_tokens(ts, [TokenType::DurationLit])?;
+        duration_from_str(duration_token.bytes()).ok_or_else(|| {
+            miette!(
+                severity = Severity::Error,
+                labels = vec![LabeledSpan::new(
+                    Some("Invalid duration literal".to_string()),
+                    duration_token.start(),
+                    duration_token.bytes().len()
+                )],
+                "Duration literal outside of range"
+            )
+        })
+    }
+}
+
+fn duration_from_str(text: &str) -> Option<Duration> {
+    let (digits, millis_per_unit) = if let Some(digits) = text.strip_suffix("ms") {
+        (digits, 1)
+    } else if let Some(digits) = text.strip_suffix('s') {
+        (digits, 1000)
+    } else {
+        (text, 1)
+    };
+    if !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(millis_per_unit))
+        .map(Duration::from_millis)
+}
+
+impl Parse for u64 {
+    fn parse(ts: &mut TokenStream<'_>) -> miette::Result<Self> {
+        let [int_token] = expect_tokens(ts, [TokenType::IntLit])?;
+        int_token.bytes().parse().map_err(|_| {
+            miette!(
+                severity = Severity::Error,
+                labels = vec![LabeledSpan::new(
+                    Some("Invalid integer literal".to_string()),
+                    int_token.start(),
+                    int_token.bytes().len()
+                )],
+                "Integer literal outside of range"
+            )
+        })
     }
 }
@@ -1,4 +1,8 @@
-use std::str::FromStr;
+use crate::ast::layout::LogicalLayout;
+use std::{
+    fmt::{self, Display, Formatter},
+    str::FromStr,
+};
 
 #[rustfmt::skip]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -37,6 +41,10 @@ pub enum KeyIdent {
     End,
     PageUp,
     PageDown,
+    PrintScreen,
+    ScrollLock,
+    Pause,
+    Menu,
 
     // Arrows
     Up,
@@ -56,6 +64,100 @@ pub enum KeyIdent {
     Period,
     Slash,
     Grave, // `
+    IntlBackslash, // Extra key next to left shift on ISO keyboards
+
+    // Mouse buttons
+    Mouse1, // left
+    Mouse2, // right
+    Mouse3, // middle
+    Mouse4, // back
+    Mouse5, // forward
+
+    // Mouse wheel
+    WheelUp,
+    WheelDown,
+    WheelLeft,
+    WheelRight,
+}
+
+impl KeyIdent {
+    #[rustfmt::skip]
+    pub const LETTERS: [KeyIdent; 26] = {
+        use KeyIdent::*;
+        [A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z]
+    };
+
+    #[rustfmt::skip]
+    pub const DIGITS: [KeyIdent; 10] = {
+        use KeyIdent::*;
+        [Num0, Num1, Num2, Num3, Num4, Num5, Num6, Num7, Num8, Num9]
+    };
+
+    #[rustfmt::skip]
+    pub const FUNCTION_KEYS: [KeyIdent; 12] = {
+        use KeyIdent::*;
+        [F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12]
+    };
+
+    /// All keys of the keyboard, mouse buttons and wheel directions are not included
+    #[rustfmt::skip]
+    pub const KEYBOARD: [KeyIdent; 88] = {
+        use KeyIdent::*;
+        [
+            A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+            Num0, Num1, Num2, Num3, Num4, Num5, Num6, Num7, Num8, Num9,
+            F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+            ShiftLeft, ShiftRight, CtrlLeft, CtrlRight, AltLeft, AltRight, MetaLeft, MetaRight,
+            Esc, Tab, CapsLock, Enter, Backspace, Space, Insert, Delete, Home, End, PageUp,
+            PageDown, PrintScreen, ScrollLock, Pause, Menu,
+            Up, Down, Left, Right,
+            Minus, Equals, LeftBracket, RightBracket, Backslash, Semicolon, Quote, Comma, Period,
+            Slash, Grave, IntlBackslash,
+        ]
+    };
+
+    pub fn is_mouse_button(self) -> bool {
+        use KeyIdent::*;
+        matches!(self, Mouse1 | Mouse2 | Mouse3 | Mouse4 | Mouse5)
+    }
+
+    /// Returns true if this key is a mouse wheel direction. Wheel events have no release.
+    pub fn is_wheel(self) -> bool {
+        use KeyIdent::*;
+        matches!(self, WheelUp | WheelDown | WheelLeft | WheelRight)
+    }
+}
+
+/// A key as written in the profile, either by its position or by the character it types. The
+/// character is looked up in the keyboard layout of the layer when the profile is compiled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KeyRef {
+    /// `q`, `pos(q)` or `"q"`, the key at the position of q on a QWERTY keyboard
+    Pos(KeyIdent),
+    /// `'q'`, the key that types q without modifiers
+    Char(char),
+}
+
+impl KeyRef {
+    pub fn resolve(self, layout: LogicalLayout) -> Option<KeyIdent> {
+        match self {
+            KeyRef::Pos(key) => Some(key),
+            KeyRef::Char(c) => layout.key_for_char(c),
+        }
+    }
+
+    pub fn is_wheel(self) -> bool {
+        matches!(self, KeyRef::Pos(key) if key.is_wheel())
+    }
+}
+
+impl Display for KeyRef {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyRef::Pos(key) => write!(f, "{key:?}"),
+            KeyRef::Char(c) => write!(f, "'{c}'"),
+        }
+    }
 }
 
 #[rustfmt::skip]
@@ -92,14 +194,26 @@ impl FromStr for KeyIdent {
             "backspace" => Backspace, "space" => Space,
             "insert" => Insert, "delete" => Delete, "home" => Home, "end" => End,
             "pageup" => PageUp, "pagedown" => PageDown,
+            "printscreen" | "prtsc" => PrintScreen, "scrolllock" => ScrollLock,
+            "pause" => Pause, "menu" => Menu,
 
             // Arrows
             "up" => Up, "down" => Down, "left" => Left, "right" => Right,
 
             // Symbols
             "-" => Minus, "=" => Equals, "[" => LeftBracket, "]" => RightBracket,
-            "\\" => Backslash, ";" => Semicolon, "'" => Quote, "," => Comma,
+            "\\" | "backslash" => Backslash, ";" => Semicolon, "'" => Quote, "," => Comma,
             "." => Period, "/" => Slash, "`" => Grave,
+            "intlbackslash" | "nonusbackslash" => IntlBackslash,
+
+            // Mouse buttons
+            "mouse1" | "mouseleft" => Mouse1, "mouse2" | "mouseright" => Mouse2,
+            "mouse3" | "mousemiddle" => Mouse3, "mouse4" | "mouseback" => Mouse4,
+            "mouse5" | "mouseforward" => Mouse5,
+
+            // Mouse wheel
+            "wheelup" => WheelUp, "wheeldown" => WheelDown,
+            "wheelleft" => WheelLeft, "wheelright" => WheelRight,
 
             _ => return Err(()),
         })
@@ -0,0 +1,310 @@
+//! Physical keyboard layouts used by `grid` blocks and logical layouts that decide which key
+//! types a character. Rows list the keys from left to right.
+use crate::ast::key::KeyIdent::{self, *};
+use std::{
+    fmt::{self, Display, Formatter},
+    str::FromStr,
+};
+
+pub struct PhysicalLayout {
+    pub name: &'static str,
+    pub rows: &'static [&'static [KeyIdent]],
+}
+
+impl PhysicalLayout {
+    /// Returns the built-in layout with the given name
+    pub fn find(name: &str) -> Option<&'static PhysicalLayout> {
+        LAYOUTS.iter().find(|layout| layout.name == name)
+    }
+
+    /// Names of all built-in layouts
+    pub fn names() -> impl Iterator<Item = &'static str> {
+        LAYOUTS.iter().map(|layout| layout.name)
+    }
+}
+
+const LAYOUTS: &[PhysicalLayout] = &[
+    PhysicalLayout {
+        name: "ansi60",
+        rows: ANSI_60,
+    },
+    PhysicalLayout {
+        name: "iso60",
+        rows: ISO_60,
+    },
+    PhysicalLayout {
+        name: "ansi_tkl",
+        rows: ANSI_TKL,
+    },
+    PhysicalLayout {
+        name: "iso_tkl",
+        rows: ISO_TKL,
+    },
+];
+
+#[rustfmt::skip]
+const ANSI_60: &[&[KeyIdent]] = &[
+    &[Esc, Num1, Num2, Num3, Num4, Num5, Num6, Num7, Num8, Num9, Num0, Minus, Equals, Backspace],
+    &[Tab, Q, W, E, R, T, Y, U, I, O, P, LeftBracket, RightBracket, Backslash],
+    &[CapsLock, A, S, D, F, G, H, J, K, L, Semicolon, Quote, Enter],
+    &[ShiftLeft, Z, X, C, V, B, N, M, Comma, Period, Slash, ShiftRight],
+    &[CtrlLeft, MetaLeft, AltLeft, Space, AltRight, MetaRight, Menu, CtrlRight],
+];
+
+// The enter key spans two rows, it is written in the home row
+#[rustfmt::skip]
+const ISO_60: &[&[KeyIdent]] = &[
+    &[Esc, Num1, Num2, Num3, Num4, Num5, Num6, Num7, Num8, Num9, Num0, Minus, Equals, Backspace],
+    &[Tab, Q, W, E, R, T, Y, U, I, O, P, LeftBracket, RightBracket],
+    &[CapsLock, A, S, D, F, G, H, J, K, L, Semicolon, Quote, Backslash, Enter],
+    &[ShiftLeft, IntlBackslash, Z, X, C, V, B, N, M, Comma, Period, Slash, ShiftRight],
+    &[CtrlLeft, MetaLeft, AltLeft, Space, AltRight, MetaRight, Menu, CtrlRight],
+];
+
+#[rustfmt::skip]
+const ANSI_TKL: &[&[KeyIdent]] = &[
+    &[Esc, F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12, PrintScreen, ScrollLock, Pause],
+    &[Grave, Num1, Num2, Num3, Num4, Num5, Num6, Num7, Num8, Num9, Num0, Minus, Equals, Backspace,
+      Insert, Home, PageUp],
+    &[Tab, Q, W, E, R, T, Y, U, I, O, P, LeftBracket, RightBracket, Backslash,
+      Delete, End, PageDown],
+    &[CapsLock, A, S, D, F, G, H, J, K, L, Semicolon, Quote, Enter],
+    &[ShiftLeft, Z, X, C, V, B, N, M, Comma, Period, Slash, ShiftRight, Up],
+    &[CtrlLeft, MetaLeft, AltLeft, Space, AltRight, MetaRight, Menu, CtrlRight, Left, Down, Right],
+];
+
+#[rustfmt::skip]
+const ISO_TKL: &[&[KeyIdent]] = &[
+    &[Esc, F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12, PrintScreen, ScrollLock, Pause],
+    &[Grave, Num1, Num2, Num3, Num4, Num5, Num6, Num7, Num8, Num9, Num0, Minus, Equals, Backspace,
+      Insert, Home, PageUp],
+    &[Tab, Q, W, E, R, T, Y, U, I, O, P, LeftBracket, RightBracket, Delete, End, PageDown],
+    &[CapsLock, A, S, D, F, G, H, J, K, L, Semicolon, Quote, Backslash, Enter],
+    &[ShiftLeft, IntlBackslash, Z, X, C, V, B, N, M, Comma, Period, Slash, ShiftRight, Up],
+    &[CtrlLeft, MetaLeft, AltLeft, Space, AltRight, MetaRight, Menu, CtrlRight, Left, Down, Right],
+];
+
+/// The layout the operating system uses to turn keys into characters. Keys are named after the
+/// character they type on QWERTY, so character keys like `'q'` are looked up through this.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum LogicalLayout {
+    #[default]
+    Qwerty,
+    Azerty,
+    Qwertz,
+    Dvorak,
+    Colemak,
+}
+
+/// The keys that type a character without modifiers, in the order of the character rows of the
+/// logical layouts
+#[rustfmt::skip]
+const CHARACTER_KEYS: [&[KeyIdent]; 5] = [
+    &[Grave, Num1, Num2, Num3, Num4, Num5, Num6, Num7, Num8, Num9, Num0, Minus, Equals],
+    &[Q, W, E, R, T, Y, U, I, O, P, LeftBracket, RightBracket, Backslash],
+    &[A, S, D, F, G, H, J, K, L, Semicolon, Quote],
+    &[Z, X, C, V, B, N, M, Comma, Period, Slash],
+    &[IntlBackslash],
+];
+
+impl LogicalLayout {
+    const ALL: [LogicalLayout; 5] = [
+        LogicalLayout::Qwerty,
+        LogicalLayout::Azerty,
+        LogicalLayout::Qwertz,
+        LogicalLayout::Dvorak,
+        LogicalLayout::Colemak,
+    ];
+
+    /// The characters typed by the keys of `CHARACTER_KEYS` without modifiers, a space marks a
+    /// key that types nothing useful. Dead keys are spaces too, they only type their accent
+    /// together with the next key.
+    #[rustfmt::skip]
+    fn character_rows(self) -> [&'static str; 5] {
+        match self {
+            LogicalLayout::Qwerty => ["`1234567890-=", "qwertyuiop[]\\", "asdfghjkl;'", "zxcvbnm,./", " "],
+            LogicalLayout::Azerty => ["²&é\"'(-è_çà)=", "azertyuiop $*", "qsdfghjklmù", "wxcvbn,;:!", "<"],
+            LogicalLayout::Qwertz => [" 1234567890ß ", "qwertzuiopü+#", "asdfghjklöä", "yxcvbnm,.-", "<"],
+            LogicalLayout::Dvorak => ["`1234567890[]", "',.pyfgcrl/=\\", "aoeuidhtns-", ";qjkxbmwvz", " "],
+            LogicalLayout::Colemak => ["`1234567890-=", "qwfpgjluy;[]\\", "arstdhneio'", "zxcvbkm,./", " "],
+        }
+    }
+
+    /// Same as `character_rows` with shift held
+    #[rustfmt::skip]
+    fn shifted_rows(self) -> [&'static str; 5] {
+        match self {
+            LogicalLayout::Qwerty => ["~!@#$%^&*()_+", "QWERTYUIOP{}|", "ASDFGHJKL:\"", "ZXCVBNM<>?", " "],
+            LogicalLayout::Azerty => [" 1234567890°+", "AZERTYUIOP £µ", "QSDFGHJKLM%", "WXCVBN?./§", ">"],
+            LogicalLayout::Qwertz => ["°!\"§$%&/()=? ", "QWERTZUIOPÜ*'", "ASDFGHJKLÖÄ", "YXCVBNM;:_", ">"],
+            LogicalLayout::Dvorak => ["~!@#$%^&*(){}", "\"<>PYFGCRL?+|", "AOEUIDHTNS_", ":QJKXBMWVZ", " "],
+            LogicalLayout::Colemak => ["~!@#$%^&*()_+", "QWFPGJLUY:{}|", "ARSTDHNEIO\"", "ZXCVBKM<>?", " "],
+        }
+    }
+
+    /// Same as `character_rows` with AltGr held, only used by the European layouts
+    #[rustfmt::skip]
+    fn alt_gr_rows(self) -> [&'static str; 5] {
+        match self {
+            LogicalLayout::Azerty => ["   #{[| \\^@]}", "  €          ", "           ", "          ", " "],
+            LogicalLayout::Qwertz => ["  ²³   {[]}\\ ", "@ €        ~ ", "           ", "      µ   ", "|"],
+            _ => ["             ", "             ", "           ", "          ", " "],
+        }
+    }
+
+    /// The character rows of every modifier that can be held, together with that modifier
+    fn levels(self) -> [(Option<KeyIdent>, [&'static str; 5]); 3] {
+        [
+            (None, self.character_rows()),
+            (Some(ShiftLeft), self.shifted_rows()),
+            (Some(AltRight), self.alt_gr_rows()),
+        ]
+    }
+
+    /// Returns the key that types the character without modifiers
+    pub fn key_for_char(self, c: char) -> Option<KeyIdent> {
+        match self.find_char(c) {
+            Some((None, key)) => Some(key),
+            _ => None,
+        }
+    }
+
+    /// Returns the key that types the character and the modifier that has to be held with it
+    pub fn find_char(self, c: char) -> Option<(Option<KeyIdent>, KeyIdent)> {
+        if c == ' ' {
+            return Some((None, Space));
+        }
+        self.levels().into_iter().find_map(|(modifier, rows)| {
+            rows.into_iter()
+                .zip(CHARACTER_KEYS)
+                .find_map(|(chars, keys)| Some(keys[chars.chars().position(|x| x == c)?]))
+                .map(|key| (modifier, key))
+        })
+    }
+
+    /// Returns the character the key types while the modifier is held
+    pub fn char_for_key(self, modifier: Option<KeyIdent>, key: KeyIdent) -> Option<char> {
+        let (_, rows) = self.levels().into_iter().find(|(m, _)| *m == modifier)?;
+        rows.into_iter()
+            .zip(CHARACTER_KEYS)
+            .find_map(|(chars, keys)| {
+                let index = keys.iter().position(|k| *k == key)?;
+                chars.chars().nth(index).filter(|c| *c != ' ')
+            })
+    }
+
+    /// All characters other than letters and digits that can be typed on the layout
+    pub fn symbols(self) -> Vec<char> {
+        let mut symbols: Vec<char> = self
+            .levels()
+            .into_iter()
+            .flat_map(|(_, rows)| rows.into_iter().flat_map(str::chars))
+            .filter(|c| !c.is_alphanumeric() && *c != ' ')
+            .collect();
+        symbols.sort_unstable();
+        symbols.dedup();
+        symbols
+    }
+}
+
+impl FromStr for LogicalLayout {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|layout| layout.to_string() == s.to_lowercase())
+            .ok_or(())
+    }
+}
+
+impl Display for LogicalLayout {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            LogicalLayout::Qwerty => "qwerty",
+            LogicalLayout::Azerty => "azerty",
+            LogicalLayout::Qwertz => "qwertz",
+            LogicalLayout::Dvorak => "dvorak",
+            LogicalLayout::Colemak => "colemak",
+        };
+        write!(f, "{name}")
+    }
+}
+
+#[cfg(test)]
+mod layout_tests {
+    use super::*;
+
+    #[test]
+    fn test_character_rows_match_keys() {
+        for layout in LogicalLayout::ALL {
+            for (_, rows) in layout.levels() {
+                for (chars, keys) in rows.into_iter().zip(CHARACTER_KEYS) {
+                    assert_eq!(chars.chars().count(), keys.len(), "{layout}: {chars}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_key_for_char() {
+        assert_eq!(LogicalLayout::Qwerty.key_for_char('q'), Some(Q));
+        assert_eq!(LogicalLayout::Azerty.key_for_char('q'), Some(A));
+        assert_eq!(LogicalLayout::Azerty.key_for_char('m'), Some(Semicolon));
+        assert_eq!(LogicalLayout::Qwertz.key_for_char('z'), Some(Y));
+        assert_eq!(LogicalLayout::Dvorak.key_for_char('s'), Some(Semicolon));
+        assert_eq!(LogicalLayout::Colemak.key_for_char('e'), Some(K));
+        assert_eq!(LogicalLayout::Azerty.key_for_char('1'), None);
+    }
+
+    #[test]
+    fn test_find_char() {
+        assert_eq!(
+            LogicalLayout::Qwerty.find_char('!'),
+            Some((Some(ShiftLeft), Num1))
+        );
+        assert_eq!(
+            LogicalLayout::Qwerty.find_char('{'),
+            Some((Some(ShiftLeft), LeftBracket))
+        );
+        assert_eq!(
+            LogicalLayout::Qwerty.find_char('A'),
+            Some((Some(ShiftLeft), A))
+        );
+        assert_eq!(LogicalLayout::Azerty.find_char('!'), Some((None, Slash)));
+        assert_eq!(
+            LogicalLayout::Azerty.find_char('{'),
+            Some((Some(AltRight), Num4))
+        );
+        assert_eq!(
+            LogicalLayout::Qwertz.find_char('@'),
+            Some((Some(AltRight), Q))
+        );
+        assert_eq!(LogicalLayout::Qwerty.find_char('€'), None);
+    }
+
+    #[test]
+    fn test_dead_keys_are_not_typable() {
+        for c in ['^', '´', '`'] {
+            assert_eq!(LogicalLayout::Qwertz.find_char(c), None, "{c}");
+        }
+        for c in ['¨', '~', '`'] {
+            assert_eq!(LogicalLayout::Azerty.find_char(c), None, "{c}");
+        }
+        // AltGr and 9 types the caret right away on AZERTY
+        assert_eq!(
+            LogicalLayout::Azerty.find_char('^'),
+            Some((Some(AltRight), Num9))
+        );
+    }
+
+    #[test]
+    fn test_physical_layouts_are_unique() {
+        for layout in LAYOUTS {
+            let keys: Vec<_> = layout.rows.iter().flat_map(|row| row.iter()).collect();
+            for (i, key) in keys.iter().enumerate() {
+                assert!(!keys[..i].contains(key), "{}: {:?}", layout.name, key);
+            }
+        }
+    }
+}
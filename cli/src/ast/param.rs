@@ -0,0 +1,468 @@
+//! Parameters are substituted before the profile is parsed. Each `$name` reference is kept as a
+//! marker and followed by the tokens of the value of the parameter, placed at the reference, so
+//! the parser reads the value as if it was written there and can still tell it came from a
+//! parameter.
+use crate::{
+    ast::{duration_from_str, key::KeyIdent},
+    lex::{Lexer, Token, TokenType},
+    utils::Span,
+};
+use itertools::Itertools;
+use miette::{miette, LabeledSpan, Severity};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::{self, Display, Formatter},
+};
+
+/// Values given on the command line for the parameters of a profile, with `--set name=value`
+#[derive(Debug, Clone, Default)]
+pub struct ParamOverrides {
+    values: BTreeMap<String, ParamOverride>,
+}
+
+#[derive(Debug, Clone)]
+struct ParamOverride {
+    text: String,
+    // The text written as a string literal, string values are substituted as one
+    literal: String,
+}
+
+/// A later value for the same name replaces the earlier one
+impl FromIterator<(String, String)> for ParamOverrides {
+    fn from_iter<I: IntoIterator<Item = (String, String)>>(iter: I) -> Self {
+        let values = iter
+            .into_iter()
+            .map(|(name, text)| {
+                let literal = format!(
+                    "\"{}\"",
+                    text.replace('\\', "\\\\")
+                        .replace('"', "\\\"")
+                        .replace('\n', "\\n")
+                );
+                (name, ParamOverride { text, literal })
+            })
+            .collect();
+        Self { values }
+    }
+}
+
+impl ParamOverrides {
+    /// The names and values of the overrides, sorted by name
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.values
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.text.as_str()))
+    }
+}
+
+impl ParamOverride {
+    /// The tokens of the value, if the text is a valid value of the given type. Keys are named
+    /// like in a declaration, so keys named by a symbol like `"-"` are strings.
+    fn tokens(&self, kind: ParamKind) -> Option<Vec<Token<'_>>> {
+        let is_name = |b: u8| b.is_ascii_alphanumeric() || b == b'_';
+        let source = match kind {
+            ParamKind::Key
+                if self.text.bytes().all(is_name) && self.text.parse::<KeyIdent>().is_ok() =>
+            {
+                &self.text
+            }
+            ParamKind::String => &self.literal,
+            ParamKind::Number if self.text.bytes().all(|b| b.is_ascii_digit()) => {
+                self.text.parse::<u64>().ok()?;
+                &self.text
+            }
+            ParamKind::Duration if duration_from_str(&self.text).is_some() => &self.text,
+            _ => return None,
+        };
+        Some(
+            Lexer::new("--set", source)
+                .filter(|t| t.kind() != TokenType::Eof)
+                .collect(),
+        )
+    }
+}
+
+/// The type of a parameter is the type of its value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParamKind {
+    Key,
+    String,
+    Number,
+    Duration,
+}
+
+impl ParamKind {
+    pub const ALL: [ParamKind; 4] = [
+        ParamKind::Key,
+        ParamKind::String,
+        ParamKind::Number,
+        ParamKind::Duration,
+    ];
+
+    /// The type of a value that starts with a token of the given type
+    pub fn of(kind: TokenType) -> Self {
+        match kind {
+            TokenType::StringLit => ParamKind::String,
+            TokenType::IntLit => ParamKind::Number,
+            TokenType::DurationLit => ParamKind::Duration,
+            _ => ParamKind::Key,
+        }
+    }
+}
+
+impl Display for ParamKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ParamKind::Key => "key",
+            ParamKind::String => "string",
+            ParamKind::Number => "number",
+            ParamKind::Duration => "duration",
+        };
+        write!(f, "{name}")
+    }
+}
+
+struct Param<'a> {
+    // The span of the value written in the declaration
+    span: Span,
+    value: Vec<Token<'a>>,
+}
+
+/// Lexes the profile and substitutes the parameters it declares with `param name = value`, using
+/// the value given on the command line instead of the declared one if there is one. The
+/// declarations are kept, the parser checks their values and where they are written.
+pub fn resolve_params<'a>(
+    lexer: Lexer<'a>,
+    overrides: &'a ParamOverrides,
+) -> miette::Result<Vec<Token<'a>>> {
+    let tokens: Vec<Token<'a>> = lexer.collect();
+    let mut params: HashMap<String, Param<'a>> = HashMap::new();
+    let mut resolved = Vec::with_capacity(tokens.len());
+
+    let mut i = 0;
+    while i < tokens.len() {
+        let declaration = match &tokens[i..] {
+            [param, name, equals, ..]
+                if param.kind() == TokenType::Param
+                    && name.kind() == TokenType::Ident
+                    && equals.kind() == TokenType::Equals =>
+            {
+                Some((*name, *equals))
+            }
+            // The parser reports a malformed declaration before anything that refers to it
+            [param, ..] if param.kind() == TokenType::Param => {
+                resolved.extend_from_slice(&tokens[i..]);
+                return Ok(resolved);
+            }
+            _ => None,
+        };
+        let Some((name_token, equals_token)) = declaration else {
+            substitute(&params, tokens[i], &mut resolved)?;
+            i += 1;
+            continue;
+        };
+        resolved.extend_from_slice(&tokens[i..i + 3]);
+        i += 3;
+
+        // The value ends at the end of the line, newlines inside of brackets are ignored like
+        // in the rest of the profile
+        let mut value: Vec<Token<'a>> = Vec::new();
+        let mut bracket_depth = 0usize;
+        while let Some(&token) = tokens.get(i) {
+            match token.kind() {
+                TokenType::Newline if bracket_depth == 0 => break,
+                TokenType::Eof => break,
+                TokenType::LParen | TokenType::LSquare => bracket_depth += 1,
+                TokenType::RParen | TokenType::RSquare => {
+                    bracket_depth = bracket_depth.saturating_sub(1)
+                }
+                _ => (),
+            }
+            let start = resolved.len();
+            substitute(&params, token, &mut resolved)?;
+            value.extend(
+                resolved[start..]
+                    .iter()
+                    .filter(|t| t.kind() != TokenType::ParamRef),
+            );
+            i += 1;
+        }
+        let (Some(first), Some(last)) = (value.first(), value.last()) else {
+            return Err(miette!(
+                severity = Severity::Error,
+                labels = vec![LabeledSpan::new(
+                    Some("expected: value".to_string()),
+                    equals_token.start(),
+                    equals_token.bytes().len()
+                )],
+                "Missing parameter value"
+            ));
+        };
+        let span = first.loc().join(last.loc());
+        let kind = ParamKind::of(first.kind());
+
+        let name = name_token.bytes();
+        if let Some(original) = params.get(name) {
+            return Err(miette!(
+                severity = Severity::Error,
+                labels = vec![
+                    LabeledSpan::new(
+                        Some("original declaration".to_string()),
+                        original.span.start(),
+                        original.span.len()
+                    ),
+                    LabeledSpan::new(
+                        Some("duplicate declaration".to_string()),
+                        name_token.start(),
+                        name_token.bytes().len()
+                    ),
+                ],
+                "Parameter '{}' is declared twice",
+                name
+            ));
+        }
+
+        if let Some(param_override) = overrides.values.get(name) {
+            value = param_override.tokens(kind).ok_or_else(|| {
+                miette!(
+                    severity = Severity::Error,
+                    labels = vec![LabeledSpan::new(
+                        Some(format!("declared as a {kind} here")),
+                        span.start(),
+                        span.len()
+                    )],
+                    "Invalid value '{}' for parameter '{}' set on the command line",
+                    param_override.text,
+                    name
+                )
+            })?;
+        }
+        params.insert(name.to_string(), Param { span, value });
+    }
+
+    if let Some(name) = overrides
+        .values
+        .keys()
+        .find(|name| !params.contains_key(*name))
+    {
+        let profile_name = tokens
+            .iter()
+            .skip_while(|t| t.kind() != TokenType::Profile)
+            .find(|t| t.kind() == TokenType::StringLit);
+        let help = if params.is_empty() {
+            "Parameters have to be declared in the profile with `param name = value`".to_string()
+        } else {
+            format!(
+                "The profile declares {}",
+                params
+                    .keys()
+                    .sorted()
+                    .map(|name| format!("'{name}'"))
+                    .join(", ")
+            )
+        };
+        return Err(miette!(
+            severity = Severity::Error,
+            labels = profile_name
+                .map(|token| LabeledSpan::new(
+                    Some(format!("does not declare '{name}'")),
+                    token.start(),
+                    token.bytes().len()
+                ))
+                .into_iter()
+                .collect::<Vec<_>>(),
+            help = help,
+            "Unknown parameter '{}' set on the command line",
+            name
+        ));
+    }
+
+    Ok(resolved)
+}
+
+/// Pushes the token, followed by the value of the parameter if it is a `$name` reference
+fn substitute<'a>(
+    params: &HashMap<String, Param<'a>>,
+    token: Token<'a>,
+    resolved: &mut Vec<Token<'a>>,
+) -> miette::Result<()> {
+    resolved.push(token);
+    if token.kind() != TokenType::ParamRef {
+        return Ok(());
+    }
+
+    let name = &token.bytes()[1..];
+    let Some(param) = params.get(name) else {
+        return Err(miette!(
+            severity = Severity::Error,
+            labels = vec![LabeledSpan::new(
+                Some("not declared".to_string()),
+                token.start(),
+                token.bytes().len()
+            )],
+            help = format!("Declare it at the top of the profile with `param {name} = value`"),
+            "Undefined parameter '{}'",
+            name
+        ));
+    };
+    resolved.extend(param.value.iter().map(|t| t.at(token.loc())));
+    Ok(())
+}
+
+#[cfg(test)]
+mod param_tests {
+    use super::*;
+    use crate::{
+        ast::{key::KeyRef, Bind, Profile, Statement, Trigger},
+        parse::{Parse, TokenStream},
+        utils::Spanned,
+    };
+
+    const PROFILE: &str = r#"profile "Test"
+param hyper = capslock
+param terminal = "alacritty"
+param timeout = 150
+param delay = $timeout
+config {
+}
+layer "base" {
+    $hyper = x
+    tap(a, capture, $delay) = run([$terminal])
+}
+"#;
+
+    /// Resolves the parameters of a profile with the given overrides and parses it
+    fn parse(source: &str, overrides: &[(&str, &str)]) -> miette::Result<Profile> {
+        let overrides: ParamOverrides = overrides
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        let lexer = Lexer::new("test", source);
+        let tokens = resolve_params(lexer, &overrides)?;
+        Profile::parse(&mut TokenStream::with_tokens(lexer, tokens))
+    }
+
+    fn statements(profile: &Profile) -> &[Spanned<Statement>] {
+        &profile.layers[0].statements
+    }
+
+    /// The key, tap timeout and run argument of the test profile
+    fn values(profile: &Profile) -> (KeyRef, usize, String) {
+        let [key_statement, tap_statement] = statements(profile) else {
+            panic!("expected two statements");
+        };
+        let Trigger::Key(key) = &key_statement.lhs.value else {
+            panic!("expected a key trigger");
+        };
+        let Trigger::Tap(_, _, Some(timeout), _) = &tap_statement.lhs.value else {
+            panic!("expected a tap with a timeout");
+        };
+        let Bind::Exec { argv, .. } = &tap_statement.rhs[0].value else {
+            panic!("expected a run bind");
+        };
+        (
+            key.value.is_basic_key().unwrap(),
+            timeout.value,
+            argv[0].value.clone(),
+        )
+    }
+
+    #[test]
+    fn test_params_are_substituted() {
+        let profile = parse(PROFILE, &[]).unwrap();
+        assert_eq!(
+            values(&profile),
+            (
+                KeyRef::Pos(KeyIdent::CapsLock),
+                150,
+                "alacritty".to_string()
+            )
+        );
+        // The span of a substituted value is the span of its reference
+        let Trigger::Key(key) = &statements(&profile)[0].lhs.value else {
+            unreachable!()
+        };
+        let start = PROFILE.find("$hyper =").unwrap();
+        assert_eq!(key.span, Span::new(start, "$hyper".len()));
+    }
+
+    #[test]
+    fn test_overrides() {
+        let profile = parse(
+            PROFILE,
+            &[
+                ("hyper", "tab"),
+                ("terminal", "my \"term\""),
+                ("timeout", "2s"),
+            ],
+        );
+        // `timeout` is a number, a duration cannot replace it
+        assert_eq!(
+            profile.unwrap_err().to_string(),
+            "Invalid value '2s' for parameter 'timeout' set on the command line"
+        );
+
+        let profile = parse(
+            PROFILE,
+            &[
+                ("hyper", "tab"),
+                ("terminal", "my \"term\""),
+                ("timeout", "300"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            values(&profile),
+            (KeyRef::Pos(KeyIdent::Tab), 300, "my \"term\"".to_string())
+        );
+    }
+
+    #[test]
+    fn test_indicator() {
+        let source = PROFILE.replace(
+            "layer \"base\" {",
+            "layer \"base\" {\n    indicator = $terminal",
+        );
+        let profile = parse(&source, &[("terminal", "FN")]).unwrap();
+        let indicator = profile.layers[0].indicator.as_ref().unwrap();
+        assert_eq!(indicator.value, "FN");
+        assert_eq!(values(&profile).0, KeyRef::Pos(KeyIdent::CapsLock));
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = |source: &str, overrides| parse(source, overrides).unwrap_err().to_string();
+
+        assert_eq!(
+            error(PROFILE, &[("missing", "a")]),
+            "Unknown parameter 'missing' set on the command line"
+        );
+        assert_eq!(
+            error(PROFILE, &[("hyper", "notakey")]),
+            "Invalid value 'notakey' for parameter 'hyper' set on the command line"
+        );
+        assert_eq!(
+            error(&PROFILE.replace("run([$terminal])", "run([$hyper])"), &[]),
+            "Parameter 'hyper' has the wrong type"
+        );
+        assert_eq!(
+            error(&PROFILE.replace("$hyper = x", "$other = x"), &[]),
+            "Undefined parameter 'other'"
+        );
+        assert_eq!(
+            error(&PROFILE.replace("param delay", "param hyper"), &[]),
+            "Parameter 'hyper' is declared twice"
+        );
+        assert_eq!(
+            error(
+                &PROFILE.replace("param timeout = 150", "param timeout ="),
+                &[]
+            ),
+            "Missing parameter value"
+        );
+        assert_eq!(
+            error(&PROFILE.replace("param delay", "param wait"), &[]),
+            "Unexpected token found"
+        );
+    }
+}
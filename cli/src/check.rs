@@ -1,25 +1,106 @@
 use crate::{
-    ast::{Bind, Config, ConfigEntry, Profile},
-    utils::Spanned,
+    ast::{
+        resolve_char, Bind, Condition, Config, ConfigData, ConfigEntry, ExecOption, Key, Layer,
+        Metadata, NameMatcher, Profile, Statement, Step, Toggle, Trigger,
+    },
+    compiled::UnicodeInputMethod,
+    desktop_entry,
+    target::TargetOs,
+    utils::{Span, Spanned},
 };
+use itertools::Itertools;
 use miette::{miette, LabeledSpan, Severity};
+use regex::Regex;
 use std::{
-    collections::{hash_map::Entry, HashMap},
+    collections::{hash_map::Entry, HashMap, HashSet},
+    env,
     mem::discriminant,
+    path::{Path, PathBuf},
 };
 
 const LARGE_TIMEOUT_WARNING_THRESHOLD: usize = 5_000;
 
 impl Profile {
-    pub fn check(&self) -> Vec<miette::Report> {
+    pub fn check(&self, target: TargetOs) -> Vec<miette::Report> {
         let mut result = vec![];
+        if let Some(metadata) = &self.metadata {
+            metadata.check(&mut result);
+        }
         self.config.check(self, &mut result);
-        self.check_layers(&mut result);
+        self.check_layers(target, &mut result);
+        self.check_toggles(&mut result);
+        // Caps word keys of the profile config are shared by the layers, report each once per
+        // keyboard layout
+        let mut checked_caps_word_keys = HashSet::new();
+        for layer in self.layers.iter() {
+            if let Some(layer_config) = &layer.config {
+                layer_config.check_layer_override(&mut result);
+            }
+            if let Some(indicator) = &layer.indicator
+                && indicator.trim().is_empty()
+            {
+                result.push(miette!(
+                    severity = Severity::Error,
+                    labels = vec![LabeledSpan::new(
+                        Some("empty indicator".to_string()),
+                        indicator.span.start(),
+                        indicator.span.len()
+                    )],
+                    help = "Remove the indicator to show nothing for this layer",
+                    "Layer indicator must not be empty"
+                ));
+            }
+
+            // Statements of `on` blocks for other platforms are checked against their own platform
+            let mut configs = HashMap::new();
+            for statement in layer.statements.iter() {
+                let platform = statement.platform.as_ref().map_or(target, |p| p.value);
+                let config = configs
+                    .entry(platform)
+                    .or_insert_with(|| self.layer_config(layer, platform));
+                // Character keys are checked once they are replaced by the keys that type them
+                let mut statement = statement.clone();
+                if let Err(report) = statement.resolve_chars(config.keyboard_layout) {
+                    result.push(report);
+                    continue;
+                }
+                statement.check(platform, config, &mut result);
+            }
+            configs
+                .entry(target)
+                .or_insert_with(|| self.layer_config(layer, target));
+            for config in configs.values() {
+                for key in config.caps_word_keys.iter() {
+                    if !checked_caps_word_keys.insert((key.span.start(), config.keyboard_layout)) {
+                        continue;
+                    }
+                    if let Err(report) =
+                        resolve_char(&mut key.value.clone(), key.span, config.keyboard_layout)
+                    {
+                        result.push(report);
+                    }
+                }
+            }
+
+            // Statements of the same `when` block share the matcher, only report it once
+            let apps = layer.statements.iter().filter_map(|s| s.app.as_ref());
+            for app in apps.unique_by(|app| app.span.start()) {
+                app.check(&mut result);
+            }
+        }
 
         result
     }
 
-    fn check_layers(&self, result: &mut Vec<miette::Report>) {
+    fn layer_config(&self, layer: &Layer, platform: TargetOs) -> ConfigData {
+        let mut data = self.config.value.clone().for_target(platform).to_data();
+        if let Some(config) = &layer.config {
+            config.value.clone().for_target(platform).apply(&mut data);
+        }
+        data
+    }
+
+    fn check_layers(&self, target: TargetOs, result: &mut Vec<miette::Report>) {
         if self.layers.is_empty() {
             result.push(miette!(
                 severity = Severity::Error,
@@ -93,10 +174,17 @@ impl Profile {
             );
         }
 
-        let default_layer = self.config.entries.iter().find_map(|c| match &c.value {
-            ConfigEntry::DefaultLayer(default_layer) => Some(default_layer),
-            _ => None,
-        });
+        let default_layer = self
+            .config
+            .platform_entries
+            .iter()
+            .filter(|(platform, _)| platform.value == target)
+            .flat_map(|(_, entries)| entries.iter())
+            .chain(self.config.entries.iter())
+            .find_map(|c| match &c.value {
+                ConfigEntry::DefaultLayer(default_layer) => Some(default_layer),
+                _ => None,
+            });
 
         let default_layer_idx = match default_layer {
             Some(s) if !name_to_index.contains_key(s.as_str()) => {
@@ -136,6 +224,83 @@ impl Profile {
         }
     }
 
+    fn check_toggles(&self, result: &mut Vec<miette::Report>) {
+        let mut seen: HashMap<&str, &Spanned<Toggle>> = HashMap::new();
+        for toggle in self.toggles.iter() {
+            if let Some(original) = seen.insert(toggle.name.as_str(), toggle) {
+                result.push(miette!(
+                    severity = Severity::Error,
+                    labels = vec![
+                        LabeledSpan::new(
+                            Some("original toggle".to_string()),
+                            original.span.start(),
+                            original.span.len()
+                        ),
+                        LabeledSpan::new(
+                            Some("duplicate toggle".to_string()),
+                            toggle.span.start(),
+                            toggle.span.len()
+                        ),
+                    ],
+                    "Duplicate toggle name '{}'",
+                    toggle.name.value
+                ));
+            }
+        }
+
+        let statements = || self.layers.iter().flat_map(|layer| layer.statements.iter());
+        let changed: HashSet<&str> = statements()
+            .flat_map(|statement| statement.rhs.iter())
+            .filter_map(|bind| match &bind.value {
+                Bind::SetToggle(name, _) | Bind::FlipToggle(name) => Some(name.as_str()),
+                _ => None,
+            })
+            .collect();
+
+        for statement in statements() {
+            let conditions = statement.conditions.iter().filter_map(|c| match &c.value {
+                Condition::ToggleOn(name) | Condition::ToggleOff(name) => Some(name),
+                _ => None,
+            });
+            let binds = statement.rhs.iter().filter_map(|b| match &b.value {
+                Bind::SetToggle(name, _) | Bind::FlipToggle(name) => Some(name),
+                _ => None,
+            });
+            for name in conditions.chain(binds) {
+                if !seen.contains_key(name.as_str()) {
+                    result.push(miette!(
+                        severity = Severity::Error,
+                        labels = vec![LabeledSpan::new(
+                            Some(format!("unknown toggle: {}", name.value)),
+                            name.span.start(),
+                            name.span.len()
+                        )],
+                        help = format!(
+                            "Declare it after the config block with `toggle {}`",
+                            name.value
+                        ),
+                        "reference to undefined toggle"
+                    ));
+                }
+            }
+        }
+
+        for toggle in self.toggles.iter() {
+            if !changed.contains(toggle.name.as_str()) {
+                result.push(miette!(
+                    severity = Severity::Warning,
+                    labels = vec![LabeledSpan::new(
+                        Some(format!("toggle {} is never changed", toggle.name.value)),
+                        toggle.span.start(),
+                        toggle.span.len()
+                    )],
+                    help = "Add a set(...) or flip(...) bind",
+                    "Toggle always keeps its initial value"
+                ));
+            }
+        }
+    }
+
     fn find_unreachable_layers(graph: &[Vec<usize>], start: usize) -> Vec<usize> {
         let mut visited = vec![false; graph.len()];
         let mut stack = vec![start];
@@ -160,57 +325,19 @@ impl Profile {
 
 impl Config {
     pub fn check(&self, profile: &Profile, result: &mut Vec<miette::Report>) {
-        let mut seen: HashMap<_, &Spanned<ConfigEntry>> = HashMap::new();
-        let mut default_layer_name = None;
-
-        for entry in self.entries.iter() {
-            let disc = discriminant(&entry.value);
-
-            if let Some(original) = seen.get(&disc) {
-                // Duplicate found: report both original and repeated
-                result.push(miette!(
-                    severity = Severity::Warning,
-                    labels = vec![
-                        LabeledSpan::new(
-                            Some("original configuration entry".to_string()),
-                            original.span.start(),
-                            original.span.len()
-                        ),
-                        LabeledSpan::new(
-                            Some("duplicate configuration entry".to_string()),
-                            entry.span.start(),
-                            entry.span.len()
-                        ),
-                    ],
-                    "Duplicate configuration entry ignored"
-                ));
-            } else {
-                seen.insert(disc, entry);
-            }
-
-            // Track default layer
-            if let ConfigEntry::DefaultLayer(name) = &entry.value && default_layer_name.is_none() {
-                default_layer_name = Some(name);
-            }
-
-            // Large timeout warning
-            if let Some(timeout) = entry.get_timeout() && timeout >= LARGE_TIMEOUT_WARNING_THRESHOLD {
-                result.push(miette!(
-                    severity = Severity::Warning,
-                    labels = vec![LabeledSpan::new(
-                        Some("timeout exceeds recommended threshold".to_string()),
-                        entry.span.start(),
-                        entry.span.len()
-                    )],
-                    "Timeout value {} exceeds threshold of {}",
-                    timeout,
-                    LARGE_TIMEOUT_WARNING_THRESHOLD
-                ));
-            }
+        for entries in self.all_entries() {
+            check_entries(entries, result);
         }
 
         // Default layer existence check
-        if let Some(layer_name) = default_layer_name {
+        let default_layer_names =
+            self.all_entries()
+                .flatten()
+                .filter_map(|entry| match &entry.value {
+                    ConfigEntry::DefaultLayer(name) => Some(name),
+                    _ => None,
+                });
+        for layer_name in default_layer_names {
             let exists = profile
                 .layers
                 .iter()
@@ -230,4 +357,879 @@ impl Config {
             }
         }
     }
+
+    /// Checks the config block of a layer, which can override everything except the default layer
+    fn check_layer_override(&self, result: &mut Vec<miette::Report>) {
+        for entries in self.all_entries() {
+            check_entries(entries, result);
+        }
+
+        for entry in self.all_entries().flatten() {
+            let name = match entry.value {
+                ConfigEntry::DefaultLayer(_) => "default_layer",
+                _ => continue,
+            };
+            result.push(miette!(
+                severity = Severity::Error,
+                labels = vec![LabeledSpan::new(
+                    Some("not allowed in a layer".to_string()),
+                    entry.span.start(),
+                    entry.span.len()
+                )],
+                help = format!("Move {name} to the config block of the profile"),
+                "{} cannot be overridden by a layer",
+                name
+            ));
+        }
+    }
+
+    fn all_entries(&self) -> impl Iterator<Item = &[Spanned<ConfigEntry>]> {
+        std::iter::once(&*self.entries).chain(self.platform_entries.iter().map(|(_, e)| &**e))
+    }
+}
+
+fn check_entries(entries: &[Spanned<ConfigEntry>], result: &mut Vec<miette::Report>) {
+    check_duplicate_entries(entries, "configuration", result);
+
+    for entry in entries.iter() {
+        // Large timeout warning
+        if let Some(timeout) = entry.get_timeout() && timeout >= LARGE_TIMEOUT_WARNING_THRESHOLD {
+            result.push(miette!(
+                severity = Severity::Warning,
+                labels = vec![LabeledSpan::new(
+                    Some("timeout exceeds recommended threshold".to_string()),
+                    entry.span.start(),
+                    entry.span.len()
+                )],
+                "Timeout value {} exceeds threshold of {}",
+                timeout,
+                LARGE_TIMEOUT_WARNING_THRESHOLD
+            ));
+        }
+    }
+}
+
+impl Metadata {
+    pub fn check(&self, result: &mut Vec<miette::Report>) {
+        check_duplicate_entries(&self.entries, "metadata", result);
+    }
+}
+
+/// Warns about entries of a block that repeat an earlier entry of the same kind. Config and
+/// metadata blocks both keep the first entry, so a duplicate is ignored rather than an error.
+fn check_duplicate_entries<T>(
+    entries: &[Spanned<T>],
+    block: &str,
+    result: &mut Vec<miette::Report>,
+) {
+    let mut seen: HashMap<_, &Spanned<T>> = HashMap::new();
+    for entry in entries.iter() {
+        if let Some(original) = seen.get(&discriminant(&entry.value)) {
+            result.push(miette!(
+                severity = Severity::Warning,
+                labels = vec![
+                    LabeledSpan::new(
+                        Some(format!("original {block} entry")),
+                        original.span.start(),
+                        original.span.len()
+                    ),
+                    LabeledSpan::new(
+                        Some(format!("duplicate {block} entry")),
+                        entry.span.start(),
+                        entry.span.len()
+                    ),
+                ],
+                "Duplicate {} entry ignored",
+                block
+            ));
+        } else {
+            seen.insert(discriminant(&entry.value), entry);
+        }
+    }
+}
+
+impl Statement {
+    pub fn check(&self, target: TargetOs, config: &ConfigData, result: &mut Vec<miette::Report>) {
+        match &self.lhs.value {
+            Trigger::Key(key) => check_wheel_release(key, result),
+            Trigger::Tap(key, ..) | Trigger::Hold(key, ..) if key.is_wheel() => {
+                result.push(miette!(
+                    severity = Severity::Error,
+                    labels = vec![LabeledSpan::new(
+                        Some("wheel direction used here".to_string()),
+                        key.span.start(),
+                        key.span.len()
+                    )],
+                    help = "Use the wheel direction on its own to trigger on each notch",
+                    "Mouse wheel cannot be tapped or held"
+                ));
+            }
+            Trigger::Sequence(steps, ..) => check_steps(steps, result),
+            Trigger::Combo(steps, ..) => {
+                for step in steps.iter() {
+                    if let Step::Key(key) = step.value {
+                        check_wheel_release(&Spanned::new(key, step.span), result);
+                    }
+                }
+                check_steps(steps, result);
+            }
+            Trigger::WindowTitle(title) => title.check(result),
+            Trigger::KeySet(keys) if keys.is_empty() => {
+                result.push(miette!(
+                    severity = Severity::Warning,
+                    labels = vec![LabeledSpan::new(
+                        Some("no keys left in this set".to_string()),
+                        self.lhs.span.start(),
+                        self.lhs.span.len()
+                    )],
+                    "Statement has no effect"
+                ));
+            }
+            _ => (),
+        }
+
+        self.check_conditions(result);
+        if !config.advanced {
+            self.check_advanced(result);
+        }
+
+        for bind in self.rhs.iter() {
+            match &bind.value {
+                Bind::Exec { argv, options } => {
+                    check_exec(bind.span, argv, options, target, result)
+                }
+                Bind::Key(key) => check_wheel_release(key, result),
+                Bind::Unicode(text) => check_unicode(text, target, result),
+                Bind::OpenApp(app_name) => {
+                    check_app_name(app_name, target, result);
+                    check_desktop_entry(app_name, target, result)
+                }
+                Bind::Notify(message) if message.trim().is_empty() => {
+                    result.push(miette!(
+                        severity = Severity::Error,
+                        labels = vec![LabeledSpan::new(
+                            Some("empty message".to_string()),
+                            message.span.start(),
+                            message.span.len()
+                        )],
+                        "Notification message must not be empty"
+                    ));
+                }
+                _ => (),
+            }
+        }
+    }
+
+    fn check_conditions(&self, result: &mut Vec<miette::Report>) {
+        let held_keys: Vec<_> = self
+            .conditions
+            .iter()
+            .filter_map(|condition| match &condition.value {
+                Condition::Held(key) | Condition::NotHeld(key) => Some((condition, key)),
+                _ => None,
+            })
+            .collect();
+
+        // Only triggers made of several events can wait for the key state, toggles work anywhere
+        if let (Some((first, _)), Some((last, _))) = (held_keys.first(), held_keys.last())
+            && !matches!(
+                self.lhs.value,
+                Trigger::Tap(..)
+                    | Trigger::Hold(..)
+                    | Trigger::Chord(..)
+                    | Trigger::Sequence(..)
+                    | Trigger::Combo(..)
+            )
+        {
+            let span = first.span.start()..last.span.end();
+            result.push(miette!(
+                severity = Severity::Error,
+                labels = vec![
+                    LabeledSpan::new(
+                        Some("condition used here".to_string()),
+                        span.start,
+                        span.len()
+                    ),
+                    LabeledSpan::new(
+                        Some("on this trigger".to_string()),
+                        self.lhs.span.start(),
+                        self.lhs.span.len()
+                    ),
+                ],
+                help = "Use a tap, hold, chord, sequence or combo trigger",
+                "Conditions are not supported on this trigger"
+            ));
+        }
+
+        for (_, key) in held_keys.iter().filter(|(_, key)| key.is_wheel()) {
+            result.push(miette!(
+                severity = Severity::Error,
+                labels = vec![LabeledSpan::new(
+                    Some("wheel direction used here".to_string()),
+                    key.span.start(),
+                    key.span.len()
+                )],
+                "Mouse wheel cannot be held"
+            ));
+        }
+
+        for (i, condition) in self.conditions.iter().enumerate() {
+            if let Some(earlier) = self.conditions[..i]
+                .iter()
+                .find(|other| other.contradicts(condition))
+            {
+                result.push(miette!(
+                    severity = Severity::Error,
+                    labels = vec![
+                        LabeledSpan::new(None, earlier.span.start(), earlier.span.len()),
+                        LabeledSpan::new(None, condition.span.start(), condition.span.len()),
+                    ],
+                    "Conditions can never be met at the same time"
+                ));
+            }
+        }
+    }
+
+    pub(crate) fn check_advanced(&self, result: &mut Vec<miette::Report>) {
+        let combo = matches!(self.lhs.value, Trigger::Combo(..)).then_some((
+            "combo trigger",
+            "Combos give raw control over every press and release, which makes it easy to leave \
+             keys stuck down",
+            self.lhs.span,
+        ));
+        let runs = self.rhs.iter().filter_map(|bind| match bind.value {
+            Bind::Run { .. } | Bind::Exec { .. } => Some((
+                "run bind",
+                "Run binds execute arbitrary commands on every trigger",
+                bind.span,
+            )),
+            _ => None,
+        });
+
+        for (feature, reason, span) in combo.into_iter().chain(runs) {
+            result.push(miette!(
+                severity = Severity::Error,
+                labels = vec![LabeledSpan::new(
+                    Some(format!("{feature} used here")),
+                    span.start(),
+                    span.len()
+                )],
+                help = format!("{reason}. Set `advanced = true` in the config block to allow them"),
+                "{} requires advanced mode",
+                feature
+            ));
+        }
+    }
+}
+
+/// Reports `wait` and `within` steps that are not placed between keys. A trailing `wait` is kept
+/// since it works like `hold`.
+fn check_steps<K>(steps: &[Spanned<Step<K>>], result: &mut Vec<miette::Report>) {
+    let leading = steps
+        .iter()
+        .take_while(|step| !matches!(step.value, Step::Key(_)));
+    let has_key = steps.iter().any(|step| matches!(step.value, Step::Key(_)));
+    let trailing = steps
+        .iter()
+        .rev()
+        .take_while(|step| has_key && !matches!(step.value, Step::Key(_)))
+        .filter(|step| matches!(step.value, Step::Within(_)));
+
+    for step in leading.chain(trailing) {
+        result.push(miette!(
+            severity = Severity::Error,
+            labels = vec![LabeledSpan::new(
+                Some("no key on this side".to_string()),
+                step.span.start(),
+                step.span.len()
+            )],
+            help = "Timing steps set the time between the keys before and after them",
+            "Timing step must be placed between keys"
+        ));
+    }
+}
+
+fn check_wheel_release(key: &Spanned<Key>, result: &mut Vec<miette::Report>) {
+    if let Key::Up(ident) = key.value
+        && ident.is_wheel()
+    {
+        result.push(miette!(
+            severity = Severity::Error,
+            labels = vec![LabeledSpan::new(
+                Some(format!("release of {}", ident.value)),
+                key.span.start(),
+                key.span.len()
+            )],
+            help = "Remove the `^` prefix",
+            "Mouse wheel events have no release"
+        ));
+    }
+}
+
+fn check_unicode(text: &Spanned<String>, target: TargetOs, result: &mut Vec<miette::Report>) {
+    if text.is_empty() {
+        result.push(miette!(
+            severity = Severity::Error,
+            labels = vec![LabeledSpan::new(
+                Some("empty string".to_string()),
+                text.span.start(),
+                text.span.len()
+            )],
+            "Unicode bind must contain at least one character"
+        ));
+        return;
+    }
+
+    let method = UnicodeInputMethod::for_target(target);
+    for c in text.chars().filter(|c| !method.supports(*c)).unique() {
+        result.push(miette!(
+            severity = Severity::Warning,
+            labels = vec![LabeledSpan::new(
+                Some(format!("U+{:04X} has no fallback", c as u32)),
+                text.span.start(),
+                text.span.len()
+            )],
+            help = format!("The {:?} input method cannot type this character", method),
+            "Character cannot be typed on {}",
+            target
+        ));
+    }
+}
+
+impl Spanned<NameMatcher> {
+    pub fn check(&self, result: &mut Vec<miette::Report>) {
+        if let NameMatcher::Regex(pattern) = &self.value
+            && let Err(err) = Regex::new(pattern)
+        {
+            result.push(miette!(
+                severity = Severity::Error,
+                labels = vec![LabeledSpan::new(
+                    Some(err.to_string()),
+                    self.span.start(),
+                    self.span.len()
+                )],
+                "Invalid name regex"
+            ));
+        }
+    }
+}
+
+/// Reports app names that cannot be quoted for `cmd.exe`, which expands variables even in quotes
+fn check_app_name(app_name: &Spanned<String>, target: TargetOs, result: &mut Vec<miette::Report>) {
+    if target == TargetOs::Windows && app_name.contains(['"', '%']) {
+        result.push(miette!(
+            severity = Severity::Error,
+            labels = vec![LabeledSpan::new(
+                Some("contains \" or %".to_string()),
+                app_name.span.start(),
+                app_name.span.len()
+            )],
+            "App names cannot contain quotes or percent signs on Windows"
+        ));
+    }
+}
+
+fn check_desktop_entry(
+    app_name: &Spanned<String>,
+    target: TargetOs,
+    result: &mut Vec<miette::Report>,
+) {
+    if target != TargetOs::Linux || TargetOs::host() != TargetOs::Linux {
+        return;
+    }
+
+    if desktop_entry::find(app_name).is_none() {
+        result.push(miette!(
+            severity = Severity::Advice,
+            labels = vec![LabeledSpan::new(
+                Some("no desktop entry found".to_string()),
+                app_name.span.start(),
+                app_name.span.len()
+            )],
+            help = "It is started with gtk-launch, which needs the app name or desktop file id \
+                    of an app in the applications directories of $XDG_DATA_HOME or \
+                    $XDG_DATA_DIRS",
+            "App '{}' is not installed",
+            app_name.value
+        ));
+    }
+}
+
+fn check_exec(
+    span: Span,
+    argv: &[Spanned<String>],
+    options: &[Spanned<ExecOption>],
+    target: TargetOs,
+    result: &mut Vec<miette::Report>,
+) {
+    let mut seen: HashMap<_, &Spanned<ExecOption>> = HashMap::new();
+    for option in options {
+        if let Some(original) = seen.insert(discriminant(&option.value), option) {
+            result.push(miette!(
+                severity = Severity::Error,
+                labels = vec![
+                    LabeledSpan::new(
+                        Some("original option".to_string()),
+                        original.span.start(),
+                        original.span.len()
+                    ),
+                    LabeledSpan::new(
+                        Some("duplicate option".to_string()),
+                        option.span.start(),
+                        option.span.len()
+                    ),
+                ],
+                "Duplicate run option"
+            ));
+        }
+    }
+
+    let timeout = options
+        .iter()
+        .find(|o| matches!(o.value, ExecOption::Timeout(_)));
+    let detach = options
+        .iter()
+        .find(|o| matches!(o.value, ExecOption::Detach(Spanned { value: true, .. })));
+    if let (Some(timeout), Some(detach)) = (timeout, detach) {
+        result.push(miette!(
+            severity = Severity::Error,
+            labels = vec![
+                LabeledSpan::new(None, timeout.span.start(), timeout.span.len()),
+                LabeledSpan::new(None, detach.span.start(), detach.span.len()),
+            ],
+            help = "A detached program keeps running after clickr stops, so it cannot be killed \
+                    after a timeout",
+            "timeout cannot be combined with detach"
+        ));
+    }
+
+    let env_vars = options.iter().flat_map(|option| match &option.value {
+        ExecOption::Env(vars) => &vars[..],
+        _ => &[],
+    });
+    let mut seen: HashMap<&str, &Spanned<String>> = HashMap::new();
+    for var in env_vars {
+        let name = &var.name;
+        if !is_env_var_name(name) {
+            result.push(miette!(
+                severity = Severity::Error,
+                labels = vec![LabeledSpan::new(
+                    Some("invalid name".to_string()),
+                    name.span.start(),
+                    name.span.len()
+                )],
+                help = "Names are made of letters, digits and underscores and do not start with a \
+                        digit",
+                "Invalid environment variable name '{}'",
+                name.value
+            ));
+        }
+        if let Some(original) = seen.insert(name.as_str(), name) {
+            result.push(miette!(
+                severity = Severity::Error,
+                labels = vec![
+                    LabeledSpan::new(
+                        Some("original variable".to_string()),
+                        original.span.start(),
+                        original.span.len()
+                    ),
+                    LabeledSpan::new(
+                        Some("duplicate variable".to_string()),
+                        name.span.start(),
+                        name.span.len()
+                    ),
+                ],
+                "Duplicate environment variable '{}'",
+                name.value
+            ));
+        }
+    }
+
+    let Some(program) = argv.first() else {
+        result.push(miette!(
+            severity = Severity::Error,
+            labels = vec![LabeledSpan::new(
+                Some("no program given".to_string()),
+                span.start(),
+                span.len()
+            )],
+            "run needs at least the program to run"
+        ));
+        return;
+    };
+
+    // The files and PATH of this machine say nothing about another platform
+    let cwd = options.iter().find_map(|option| match &option.value {
+        ExecOption::Cwd(dir) => Some(dir.as_str()),
+        _ => None,
+    });
+    if target == TargetOs::host() && program_exists(program, cwd) == Some(false) {
+        let label = if is_bare_name(program) {
+            "not found on PATH"
+        } else {
+            "file not found"
+        };
+        result.push(miette!(
+            severity = Severity::Warning,
+            labels = vec![LabeledSpan::new(
+                Some(label.to_string()),
+                program.span.start(),
+                program.span.len()
+            )],
+            "Program '{}' does not exist",
+            program.value
+        ));
+    }
+}
+
+fn is_env_var_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn is_bare_name(program: &str) -> bool {
+    !program.starts_with('~') && !program.contains(['/', std::path::MAIN_SEPARATOR])
+}
+
+fn expand_home(path: &str) -> Option<PathBuf> {
+    let home = || env::var_os(if cfg!(windows) { "USERPROFILE" } else { "HOME" });
+    match path.strip_prefix('~') {
+        Some("") => home().map(PathBuf::from),
+        Some(rest) if rest.starts_with(['/', std::path::MAIN_SEPARATOR]) => {
+            home().map(|home| Path::new(&home).join(&rest[1..]))
+        }
+        _ => Some(PathBuf::from(path)),
+    }
+}
+
+/// Returns whether the program exists. Bare names are looked up on the PATH and relative paths
+/// in `cwd`. `None` if that cannot be known, like for a relative path without an absolute `cwd`.
+fn program_exists(program: &str, cwd: Option<&str>) -> Option<bool> {
+    if !is_bare_name(program) {
+        let path = expand_home(program)?;
+        if path.is_absolute() {
+            return Some(path.is_file());
+        }
+        let cwd = expand_home(cwd?)?;
+        return cwd.is_absolute().then(|| cwd.join(path).is_file());
+    }
+
+    let extensions: &[&str] = if cfg!(windows) {
+        &["", ".exe", ".cmd", ".bat"]
+    } else {
+        &[""]
+    };
+    Some(env::var_os("PATH").is_some_and(|path| {
+        env::split_paths(&path).any(|dir| {
+            extensions
+                .iter()
+                .any(|ext| dir.join(format!("{program}{ext}")).is_file())
+        })
+    }))
+}
+
+#[cfg(test)]
+mod check_tests {
+    use super::*;
+    use crate::{
+        lex::Lexer,
+        parse::{Parse, TokenStream},
+    };
+
+    fn check(source: &str, target: TargetOs) -> Vec<String> {
+        let mut ts = TokenStream::new(Lexer::new("test", source));
+        let profile = Profile::parse(&mut ts)
+            .unwrap_or_else(|err| panic!("{:?}", err.with_source_code(source.to_string())));
+        profile
+            .check(target)
+            .iter()
+            .map(|report| report.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_other_platforms_are_checked() {
+        let source = r#"profile "Test"
+config {
+}
+layer "base" {
+    on macos {
+        a = layer("missing")
+        b = unicode("")
+    }
+}
+"#;
+        assert_eq!(
+            check(source, TargetOs::Linux),
+            [
+                "reference to undefined layer",
+                "Unicode bind must contain at least one character"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_mouse_wheel_has_no_release() {
+        let source = r#"profile "Test"
+config {
+}
+layer "base" {
+    ^wheelup = a
+    tap(wheeldown) = b
+    c = ^wheelleft
+    wheelright = mouse5
+}
+"#;
+        assert_eq!(
+            check(source, TargetOs::Linux),
+            [
+                "Mouse wheel events have no release",
+                "Mouse wheel cannot be tapped or held",
+                "Mouse wheel events have no release"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unicode() {
+        let source = r#"profile "Test"
+config {
+}
+layer "base" {
+    a = unicode("")
+    b = unicode("→😀")
+}
+"#;
+        assert_eq!(
+            check(source, TargetOs::Linux),
+            ["Unicode bind must contain at least one character"]
+        );
+        assert_eq!(
+            check(source, TargetOs::Macos),
+            [
+                "Unicode bind must contain at least one character",
+                "Character cannot be typed on macos"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_name_regex() {
+        let source = r#"profile "Test"
+config {
+}
+layer "base" {
+    window_title(~"Fire(fox") = layer("base")
+    when app(~"[") {
+        a = b
+    }
+    when app(~"Code|Codium") {
+        a = c
+    }
+}
+"#;
+        assert_eq!(
+            check(source, TargetOs::Linux),
+            ["Invalid name regex", "Invalid name regex"]
+        );
+    }
+
+    #[test]
+    fn test_layer_config_overrides() {
+        let source = r#"profile "Test"
+config {
+}
+layer "base" {
+    config {
+        default_layer = "base"
+        tap_timeout = 120
+        tap_timeout = 10000
+    }
+}
+"#;
+        assert_eq!(
+            check(source, TargetOs::Linux),
+            [
+                "Duplicate configuration entry ignored",
+                "Timeout value 10000 exceeds threshold of 5000",
+                "default_layer cannot be overridden by a layer"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_default_layer_of_on_blocks() {
+        let source = r#"profile "Test"
+config {
+    on linux {
+        default_layer = "missing"
+    }
+}
+layer "base" {
+}
+"#;
+        assert_eq!(
+            check(source, TargetOs::Linux),
+            [
+                "default_layer 'missing' does not exist in profile layers",
+                "default layer not found"
+            ]
+        );
+        assert_eq!(
+            check(source, TargetOs::Macos),
+            ["default_layer 'missing' does not exist in profile layers"]
+        );
+    }
+
+    #[test]
+    fn test_character_keys_use_the_layout_of_the_layer() {
+        let source = r#"profile "Test"
+config {
+    caps_word_keys = ['^']
+    keyboard_layout = "qwertz"
+}
+layer "base" {
+    a = layer("de")
+    b = layer("us")
+}
+layer "de" {
+    tap('ü') = a
+}
+layer "us" {
+    config {
+        keyboard_layout = "qwerty"
+    }
+    tap('ü') = a
+}
+"#;
+        assert_eq!(
+            check(source, TargetOs::Linux),
+            ["Character not found in the keyboard layout"; 3]
+        );
+    }
+
+    #[test]
+    fn test_toggles() {
+        let source = r#"profile "Test"
+config {
+}
+toggle gaming
+toggle arrows = true
+toggle gaming
+layer "base" {
+    f12 = flip(gaming)
+    a if missing = b
+    b = set(other, true)
+}
+"#;
+        assert_eq!(
+            check(source, TargetOs::Linux),
+            [
+                "Duplicate toggle name 'gaming'",
+                "reference to undefined toggle",
+                "reference to undefined toggle",
+                "Toggle always keeps its initial value"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_empty_text() {
+        let source = r#"profile "Test"
+config {
+}
+layer "base" {
+    indicator = " "
+    a = notify("")
+}
+"#;
+        assert_eq!(
+            check(source, TargetOs::Linux),
+            [
+                "Layer indicator must not be empty",
+                "Notification message must not be empty"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_duplicate_entries_are_warnings() {
+        let source = r#"profile "Test"
+metadata {
+    author = "a"
+    author = "b"
+}
+config {
+    advanced = true
+    advanced = false
+}
+layer "base" {
+}
+"#;
+        let mut ts = TokenStream::new(Lexer::new("test", source));
+        let reports = Profile::parse(&mut ts).unwrap().check(TargetOs::Linux);
+        let found: Vec<_> = reports
+            .iter()
+            .map(|report| (report.to_string(), report.severity()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (
+                    "Duplicate metadata entry ignored".to_string(),
+                    Some(Severity::Warning)
+                ),
+                (
+                    "Duplicate configuration entry ignored".to_string(),
+                    Some(Severity::Warning)
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_exec_options() {
+        let source = r#"profile "Test"
+config {
+    advanced = true
+}
+layer "base" {
+    a = run(["true"], env = {"A": "1", "2B": "2", "A": "3"}, timeout = 5s, detach = true)
+}
+"#;
+        assert_eq!(
+            check(source, TargetOs::Linux),
+            [
+                "timeout cannot be combined with detach",
+                "Invalid environment variable name '2B'",
+                "Duplicate environment variable 'A'"
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_program_exists() {
+        assert_eq!(program_exists("sh", None), Some(true));
+        assert_eq!(program_exists("sh", Some("/nonexistent")), Some(true));
+        assert_eq!(program_exists("/bin/sh", None), Some(true));
+        assert_eq!(program_exists("./sh", Some("/bin")), Some(true));
+        assert_eq!(program_exists("./missing", Some("/bin")), Some(false));
+        assert_eq!(program_exists("./sh", None), None);
+        assert_eq!(program_exists("./sh", Some("bin")), None);
+    }
+
+    #[test]
+    fn test_expand_home() {
+        let home = env::var_os(if cfg!(windows) { "USERPROFILE" } else { "HOME" });
+        assert_eq!(expand_home("~"), home.clone().map(PathBuf::from));
+        assert_eq!(
+            expand_home("~/bin/tool"),
+            home.map(|home| Path::new(&home).join("bin/tool"))
+        );
+        assert_eq!(expand_home("~user/bin"), Some(PathBuf::from("~user/bin")));
+    }
 }
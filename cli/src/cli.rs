@@ -1,6 +1,6 @@
 use clap::{Parser, Subcommand};
 
-use crate::cli::styling::CLAP_STYLING;
+use crate::{cli::styling::CLAP_STYLING, target::TargetOs};
 
 mod styling;
 
@@ -21,6 +21,14 @@ pub enum ClickrSubcommand {
         /// Profile to use
         #[clap(short = 'p', long = "profile")]
         profile: Option<String>,
+
+        /// Platform to check the profile for, defaults to the current platform
+        #[clap(short = 't', long = "target", visible_alias = "target-os")]
+        target: Option<TargetOs>,
+
+        /// Override the value of a profile parameter, can be given multiple times
+        #[clap(long = "set", value_name = "NAME=VALUE", value_parser = parse_param_override)]
+        set: Vec<(String, String)>,
     },
 
     /// Check and activate the given profile
@@ -29,6 +37,33 @@ pub enum ClickrSubcommand {
         /// Profile to use
         #[clap(short = 'p', long = "profile")]
         profile: Option<String>,
+
+        /// Platform to compile the profile for, defaults to the current platform
+        #[clap(short = 't', long = "target", visible_alias = "target-os")]
+        target: Option<TargetOs>,
+
+        /// Override the value of a profile parameter, can be given multiple times
+        #[clap(long = "set", value_name = "NAME=VALUE", value_parser = parse_param_override)]
+        set: Vec<(String, String)>,
+    },
+
+    /// Check the given profile and print the compiled profile sent to the daemon
+    Compile {
+        /// Profile to use
+        #[clap(short = 'p', long = "profile")]
+        profile: Option<String>,
+
+        /// Platform to compile the profile for, defaults to the current platform
+        #[clap(short = 't', long = "target", visible_alias = "target-os")]
+        target: Option<TargetOs>,
+
+        /// Override the value of a profile parameter, can be given multiple times
+        #[clap(long = "set", value_name = "NAME=VALUE", value_parser = parse_param_override)]
+        set: Vec<(String, String)>,
+
+        /// File to write the compiled profile to instead of stdout
+        #[clap(short = 'o', long = "output")]
+        output: Option<String>,
     },
 
     /// Report the status of the clickr daemon
@@ -51,3 +86,10 @@ pub enum ClickrSubcommand {
     #[clap(visible_alias("r"))]
     Resume {},
 }
+
+/// Splits a `--set name=value` argument
+fn parse_param_override(arg: &str) -> Result<(String, String), String> {
+    arg.split_once('=')
+        .map(|(name, value)| (name.trim().to_string(), value.to_string()))
+        .ok_or_else(|| format!("expected NAME=VALUE, found '{arg}'"))
+}
@@ -1,10 +1,17 @@
 #![allow(unstable_name_collisions)]
 use itertools::Itertools;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 use crate::{
-    ast::{self, key::KeyIdent, ConfigData},
-    utils::{Span, Spanned},
+    ast::{
+        self,
+        key::{KeyIdent, KeyRef},
+        param::ParamOverrides,
+        ConfigData,
+    },
+    desktop_entry::{self, shell_quote},
+    target::TargetOs,
+    utils::{fnv1a_hash, Span, Spanned},
 };
 use miette::{LabeledSpan, Severity};
 use serde::{Serialize, Serializer};
@@ -12,13 +19,40 @@ use serde::{Serialize, Serializer};
 #[derive(Debug, Serialize)]
 pub struct Profile {
     pub profile_name: String,
+    pub metadata: Metadata,
     pub default_layer: usize,
+    /// The state table, binds and remappings refer to toggles by their index
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub toggles: Vec<Toggle>,
     pub layers: Vec<Layer>,
 }
 
+#[derive(Debug, Serialize)]
+pub struct Toggle {
+    pub toggle_name: String,
+    pub initial: bool,
+}
+
+#[derive(Debug, Serialize, Default)]
+pub struct Metadata {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keyboard: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    pub source_hash: String,
+}
+
 #[derive(Debug, Serialize)]
 pub struct Layer {
     pub layer_name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub indicator: Option<String>,
     pub remappings: Vec<Remapping>,
 }
 
@@ -29,10 +63,52 @@ pub enum Remapping {
     Sequence(SequenceRemapping),
 }
 
+impl Remapping {
+    pub fn app(&self) -> Option<&NameMatcher> {
+        match self {
+            Remapping::Basic(basic) => basic.app.as_ref(),
+            Remapping::Sequence(sequence) => sequence.app.as_ref(),
+        }
+    }
+
+    pub fn toggles(&self) -> &[ToggleCondition] {
+        match self {
+            Remapping::Basic(basic) => &basic.toggles,
+            Remapping::Sequence(sequence) => &sequence.toggles,
+        }
+    }
+
+    fn set_app(&mut self, app: Option<NameMatcher>) {
+        match self {
+            Remapping::Basic(basic) => basic.app = app,
+            Remapping::Sequence(sequence) => sequence.app = app,
+        }
+    }
+
+    fn set_toggles(&mut self, toggles: Vec<ToggleCondition>) {
+        match self {
+            Remapping::Basic(basic) => basic.toggles = toggles,
+            Remapping::Sequence(sequence) => sequence.toggles = toggles,
+        }
+    }
+
+    /// Checks the conditions before the first event of a sequence, basic remappings never have
+    /// conditions after checking
+    fn add_conditions(&mut self, conditions: &[AdvancedTrigger]) {
+        if let Remapping::Sequence(sequence) = self {
+            sequence.triggers.splice(0..0, conditions.iter().copied());
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Eq, PartialEq, Hash)]
 pub struct BasicRemapping {
     pub trigger: BasicTrigger,
     pub binds: Vec<Bind>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub app: Option<NameMatcher>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub toggles: Vec<ToggleCondition>,
 }
 
 #[derive(Debug, Serialize, Eq, PartialEq, Hash)]
@@ -40,6 +116,24 @@ pub struct SequenceRemapping {
     pub triggers: Vec<AdvancedTrigger>,
     pub binds: Vec<Bind>,
     pub behavior: Behavior,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub app: Option<NameMatcher>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub toggles: Vec<ToggleCondition>,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct ToggleCondition {
+    pub toggle: usize,
+    pub value: bool,
+}
+
+#[derive(Debug, Serialize, Clone, Eq, PartialEq, Hash)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum NameMatcher {
+    Exact { value: String },
+    Glob { pattern: String },
+    Regex { pattern: String },
 }
 
 #[derive(Debug, Serialize, Clone, Eq, PartialEq, Hash)]
@@ -53,9 +147,27 @@ pub enum BasicTrigger {
         #[serde(serialize_with = "serialize_key")]
         value: KeyIdent,
     },
+    MouseButtonPress {
+        #[serde(serialize_with = "serialize_key")]
+        button: KeyIdent,
+    },
+    MouseButtonRelease {
+        #[serde(serialize_with = "serialize_key")]
+        button: KeyIdent,
+    },
+    Wheel {
+        #[serde(serialize_with = "serialize_key")]
+        direction: KeyIdent,
+    },
     AppFocused {
         app_name: String,
     },
+    AppUnfocused {
+        app_name: String,
+    },
+    WindowTitle {
+        title: NameMatcher,
+    },
 }
 
 #[derive(Debug, Serialize, Clone, Copy, Eq, PartialEq, Hash)]
@@ -69,12 +181,35 @@ pub enum AdvancedTrigger {
         #[serde(serialize_with = "serialize_key")]
         value: KeyIdent,
     },
+    MouseButtonPress {
+        #[serde(serialize_with = "serialize_key")]
+        button: KeyIdent,
+    },
+    MouseButtonRelease {
+        #[serde(serialize_with = "serialize_key")]
+        button: KeyIdent,
+    },
+    Wheel {
+        #[serde(serialize_with = "serialize_key")]
+        direction: KeyIdent,
+    },
     MinimumWait {
         duration: usize,
     },
     MaximumWait {
         duration: usize,
     },
+    PriorIdle {
+        duration: usize,
+    },
+    KeyHeld {
+        #[serde(serialize_with = "serialize_key")]
+        value: KeyIdent,
+    },
+    KeyNotHeld {
+        #[serde(serialize_with = "serialize_key")]
+        value: KeyIdent,
+    },
 }
 
 #[derive(Debug, Serialize, Eq, PartialEq, Hash)]
@@ -88,9 +223,32 @@ pub enum Bind {
         #[serde(serialize_with = "serialize_key")]
         value: KeyIdent,
     },
+    PressMouseButton {
+        #[serde(serialize_with = "serialize_key")]
+        button: KeyIdent,
+    },
+    ReleaseMouseButton {
+        #[serde(serialize_with = "serialize_key")]
+        button: KeyIdent,
+    },
+    Scroll {
+        #[serde(serialize_with = "serialize_key")]
+        direction: KeyIdent,
+    },
+    MoveMouse {
+        dx: i32,
+        dy: i32,
+    },
     SwitchLayer {
         value: usize,
     },
+    SetToggle {
+        toggle: usize,
+        value: bool,
+    },
+    FlipToggle {
+        toggle: usize,
+    },
     Wait {
         value: usize,
     },
@@ -98,6 +256,30 @@ pub enum Bind {
         interpreter: String,
         script: String,
     },
+    Exec {
+        argv: Vec<String>,
+        #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+        env: BTreeMap<String, String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        cwd: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        timeout: Option<usize>,
+        detach: bool,
+    },
+    Notify {
+        message: String,
+    },
+    TypeUnicode {
+        value: String,
+        method: UnicodeInputMethod,
+    },
+    /// Shifts `shifted_keys` until a key outside of `shifted_keys` and `continue_keys` is pressed
+    CapsWord {
+        #[serde(serialize_with = "serialize_keys")]
+        shifted_keys: Vec<KeyIdent>,
+        #[serde(serialize_with = "serialize_keys")]
+        continue_keys: Vec<KeyIdent>,
+    },
 }
 
 #[derive(Debug, Serialize, Clone, Copy, Eq, PartialEq, Hash)]
@@ -108,11 +290,130 @@ pub enum Behavior {
     Default,
 }
 
+#[derive(Debug, Serialize, Clone, Copy, Eq, PartialEq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum UnicodeInputMethod {
+    /// Windows `SendInput` with `KEYEVENTF_UNICODE`
+    SendInput,
+    /// The macOS "Unicode Hex Input" source, option + the UTF-16 code in hex
+    HexInput,
+    /// The IBus and GTK ctrl + shift + u sequence followed by the code point in hex
+    CtrlShiftU,
+}
+
+impl UnicodeInputMethod {
+    pub fn for_target(target: TargetOs) -> Self {
+        match target {
+            TargetOs::Windows => UnicodeInputMethod::SendInput,
+            TargetOs::Macos => UnicodeInputMethod::HexInput,
+            TargetOs::Linux => UnicodeInputMethod::CtrlShiftU,
+        }
+    }
+
+    pub fn supports(self, c: char) -> bool {
+        match self {
+            _ if c.is_control() => false,
+            // Hex input takes a single UTF-16 code unit
+            UnicodeInputMethod::HexInput => c <= '\u{FFFF}',
+            UnicodeInputMethod::SendInput | UnicodeInputMethod::CtrlShiftU => true,
+        }
+    }
+}
+
+impl BasicTrigger {
+    fn press(key: KeyIdent) -> Self {
+        if key.is_mouse_button() {
+            BasicTrigger::MouseButtonPress { button: key }
+        } else if key.is_wheel() {
+            BasicTrigger::Wheel { direction: key }
+        } else {
+            BasicTrigger::KeyPress { value: key }
+        }
+    }
+
+    /// The trigger for releasing the given key or button. Wheel directions are never released.
+    fn release(key: KeyIdent) -> Option<Self> {
+        if key.is_mouse_button() {
+            Some(BasicTrigger::MouseButtonRelease { button: key })
+        } else if key.is_wheel() {
+            None
+        } else {
+            Some(BasicTrigger::KeyRelease { value: key })
+        }
+    }
+}
+
+impl AdvancedTrigger {
+    fn press(key: KeyIdent) -> Self {
+        if key.is_mouse_button() {
+            AdvancedTrigger::MouseButtonPress { button: key }
+        } else if key.is_wheel() {
+            AdvancedTrigger::Wheel { direction: key }
+        } else {
+            AdvancedTrigger::KeyPress { value: key }
+        }
+    }
+
+    /// The trigger for releasing the given key or button. Wheel directions are never released.
+    fn release(key: KeyIdent) -> Option<Self> {
+        if key.is_mouse_button() {
+            Some(AdvancedTrigger::MouseButtonRelease { button: key })
+        } else if key.is_wheel() {
+            None
+        } else {
+            Some(AdvancedTrigger::KeyRelease { value: key })
+        }
+    }
+
+    fn is_input(&self) -> bool {
+        !matches!(
+            self,
+            AdvancedTrigger::MinimumWait { .. }
+                | AdvancedTrigger::MaximumWait { .. }
+                | AdvancedTrigger::PriorIdle { .. }
+                | AdvancedTrigger::KeyHeld { .. }
+                | AdvancedTrigger::KeyNotHeld { .. }
+        )
+    }
+}
+
+impl Bind {
+    fn press(key: KeyIdent) -> Self {
+        if key.is_mouse_button() {
+            Bind::PressMouseButton { button: key }
+        } else if key.is_wheel() {
+            Bind::Scroll { direction: key }
+        } else {
+            Bind::PressKey { value: key }
+        }
+    }
+
+    /// The bind that releases the given key or button. Wheel directions are never released.
+    fn release(key: KeyIdent) -> Option<Self> {
+        if key.is_mouse_button() {
+            Some(Bind::ReleaseMouseButton { button: key })
+        } else if key.is_wheel() {
+            None
+        } else {
+            Some(Bind::ReleaseKey { value: key })
+        }
+    }
+}
+
 impl ast::Profile {
-    pub fn compile(self) -> miette::Result<Profile> {
+    /// Compiles the profile for `target`. `source` is the text it was parsed from and `params`
+    /// the parameters set on the command line, they are only used for the source hash.
+    pub fn compile(
+        self,
+        target: TargetOs,
+        source: &str,
+        params: &ParamOverrides,
+    ) -> miette::Result<Profile> {
         let ast::Profile {
             name,
+            metadata,
             config,
+            toggles,
             layers,
         } = self;
         let config_data = config.to_data();
@@ -122,8 +423,17 @@ impl ast::Profile {
             .enumerate()
             .map(|(i, l)| (l.name.value.clone(), i))
             .collect();
+        let toggle_names: HashMap<String, usize> = toggles
+            .iter()
+            .enumerate()
+            .map(|(i, t)| (t.name.value.clone(), i))
+            .collect();
         Ok(Profile {
             profile_name: name.value,
+            metadata: Metadata {
+                source_hash: source_hash(source, params),
+                ..metadata.map(|m| m.value.compile()).unwrap_or_default()
+            },
             default_layer: config_data
                 .default_layer
                 .as_ref()
@@ -131,163 +441,471 @@ impl ast::Profile {
                     layers
                         .iter()
                         .position(|l| &l.name.value == layer_name)
-                        .expect("default layer should exist after validating the profile")
+                        .ok_or_else(|| {
+                            miette::miette!(
+                                severity = Severity::Error,
+                                "default_layer '{}' does not exist in profile layers",
+                                layer_name
+                            )
+                        })
                 })
+                .transpose()?
                 .unwrap_or(0),
+            toggles: toggles
+                .iter()
+                .map(|t| Toggle {
+                    toggle_name: t.name.value.clone(),
+                    initial: t.initial,
+                })
+                .collect(),
             layers: layers
                 .into_iter()
-                .map(|l| l.value.compile(&config_data, layer_names.clone()))
+                .map(|l| {
+                    let layer_config = l.config_data(&config_data);
+                    l.value.compile(
+                        &layer_config,
+                        layer_names.clone(),
+                        toggle_names.clone(),
+                        target,
+                    )
+                })
                 .collect::<Result<Vec<_>, _>>()?,
         })
     }
 }
 
+/// Hashes the source together with the parameters set on the command line, which change the
+/// profile as much as editing the declarations would
+fn source_hash(source: &str, params: &ParamOverrides) -> String {
+    let mut hashed = source.to_string();
+    for (name, value) in params.iter() {
+        hashed.push_str(&format!("\0{name}={value}"));
+    }
+    format!("{:016x}", fnv1a_hash(hashed.as_bytes()))
+}
+
+impl ast::Metadata {
+    /// The source hash is left empty, it is filled in by [`ast::Profile::compile`]
+    fn compile(self) -> Metadata {
+        let mut metadata = Metadata::default();
+        // Duplicates are reported by the checker, the first entry wins
+        for entry in self.entries.into_iter().rev() {
+            match entry.value {
+                ast::MetadataEntry::Author(v) => metadata.author = Some(v.value),
+                ast::MetadataEntry::Version(v) => metadata.version = Some(v.value),
+                ast::MetadataEntry::Description(v) => metadata.description = Some(v.value),
+                ast::MetadataEntry::Keyboard(v) => metadata.keyboard = Some(v.value),
+                ast::MetadataEntry::Tags(tags) => {
+                    metadata.tags = tags.into_iter().map(|t| t.value).collect()
+                }
+            }
+        }
+        metadata
+    }
+}
+
 struct LayerCompilationState {
-    basic_remappings: HashMap<BasicTrigger, Span>,
-    sequence_remappings: HashMap<AdvancedTrigger, SequenceTrie>,
+    /// Remappings seen so far, grouped by the app and toggle values they are scoped to
+    conflict_tables: HashMap<Scope, ConflictTable>,
     config: ConfigData,
     layers: HashMap<String, usize>,
+    toggles: HashMap<String, usize>,
+    target: TargetOs,
+    /// The releases of keys remapped only for some toggle values, one per key and app
+    toggle_releases: Vec<BasicRemapping>,
 }
 impl LayerCompilationState {
-    fn try_insert_remappings(
+    /// Compiles a statement and checks it against the statements compiled before it. A statement
+    /// that conflicts with one of higher precedence is left out instead of being reported.
+    fn compile_statement(
+        &mut self,
+        mut statement: Spanned<ast::Statement>,
+        precedence: Precedence,
+    ) -> miette::Result<Vec<Remapping>> {
+        let span = statement.span;
+        statement.resolve_chars(self.config.keyboard_layout)?;
+        // The checker explains this, the error only keeps compile from running without checking
+        if !self.config.advanced {
+            let mut reports = Vec::new();
+            statement.check_advanced(&mut reports);
+            if let Some(report) = reports.into_iter().next() {
+                return Err(report);
+            }
+        }
+        let app = statement.app.as_ref().map(|app| app.value.compile());
+        let prior_idle = statement.lhs.value.prior_idle(&self.config);
+        let conditions: Vec<_> = prior_idle
+            .map(|duration| AdvancedTrigger::PriorIdle { duration })
+            .into_iter()
+            .chain(statement.conditions.iter().filter_map(|c| c.compile()))
+            .collect();
+        let toggles: Vec<_> = statement
+            .conditions
+            .iter()
+            .filter_map(|c| c.compile_toggle(&self.toggles))
+            .sorted()
+            .dedup()
+            .collect();
+        let released_key = match &statement.lhs.value {
+            ast::Trigger::Key(key) => match key.value {
+                ast::Key::Unspecified(key) => Some(key.value.resolved()),
+                _ => None,
+            },
+            _ => None,
+        };
+        let mut remappings = ast::Statement::compile(statement, self);
+        for remapping in remappings.iter_mut() {
+            remapping.set_app(app.clone());
+            remapping.set_toggles(toggles.clone());
+            remapping.add_conditions(&conditions);
+        }
+
+        if let Some(existing) = self.find_conflict(&remappings) {
+            if existing.precedence < precedence {
+                return Ok(Vec::new());
+            }
+            return Err(conflicting_binds(existing.span, span));
+        }
+        self.insert_remappings(&remappings, Origin { span, precedence })?;
+
+        // A toggle can change while the key is down, so its release must not depend on the toggle
+        if !toggles.is_empty()
+            && let Some(key) = released_key
+            && let Some(release) = BasicTrigger::release(key)
+        {
+            let (releases, others): (Vec<_>, Vec<_>) = remappings.into_iter().partition(
+                |remapping| matches!(remapping, Remapping::Basic(basic) if basic.trigger == release),
+            );
+            for remapping in releases {
+                if let Remapping::Basic(basic) = remapping {
+                    self.add_toggle_release(key, basic);
+                }
+            }
+            remappings = others;
+        }
+        Ok(remappings)
+    }
+
+    /// Merges the release of a key remapped for some toggle values into one remapping for the key
+    /// that applies whatever the toggles are. It releases every key the statements for the key
+    /// press, and the key itself in case its press was let through.
+    fn add_toggle_release(&mut self, key: KeyIdent, release: BasicRemapping) {
+        let existing = self
+            .toggle_releases
+            .iter_mut()
+            .find(|existing| existing.trigger == release.trigger && existing.app == release.app);
+        let merged = match existing {
+            Some(existing) => existing,
+            None => {
+                self.toggle_releases.push(BasicRemapping {
+                    trigger: release.trigger,
+                    binds: Bind::release(key).into_iter().collect(),
+                    app: release.app,
+                    toggles: vec![],
+                });
+                self.toggle_releases.last_mut().unwrap()
+            }
+        };
+        for bind in release.binds {
+            if !merged.binds.contains(&bind) {
+                merged.binds.push(bind);
+            }
+        }
+    }
+
+    /// The statement an earlier remapping comes from, if one of the remappings conflicts with it.
+    /// Remappings must not overlap with the remappings of their own scope or of other scopes that
+    /// can apply at the same time.
+    fn find_conflict(&self, remappings: &[Remapping]) -> Option<Origin> {
+        remappings.iter().find_map(|remapping| {
+            let scope = Scope::of(remapping);
+            self.conflict_tables
+                .iter()
+                .filter(|(other, _)| other.overlaps(&scope))
+                .find_map(|(_, table)| table.find_conflict(remapping))
+        })
+    }
+
+    fn insert_remappings(
         &mut self,
         remappings: &[Remapping],
-        span: Span,
+        origin: Origin,
     ) -> miette::Result<()> {
         for remapping in remappings {
-            match remapping {
-                Remapping::Basic(basic_remapping) => {
-                    match self.basic_remappings.entry(basic_remapping.trigger.clone()) {
-                        std::collections::hash_map::Entry::Occupied(occupied_entry) => {
-                            return Err(conflicting_binds(*occupied_entry.get(), span))
-                        }
-                        std::collections::hash_map::Entry::Vacant(vacant_entry) => {
-                            vacant_entry.insert_entry(span);
-                        }
-                    }
-                }
-                Remapping::Sequence(SequenceRemapping {
-                    triggers,
-                    binds: _,
-                    behavior,
-                }) => {
-                    if triggers.is_empty() {
-                        return Err(miette::miette!(
-                            severity = Severity::Error,
-                            labels = vec![LabeledSpan::new(
-                                Some("empty sequence found here".to_string()),
-                                span.start(),
-                                span.end()
-                            ),],
-                            "empty sequences not allowed"
-                        ));
-                    }
+            if let Remapping::Sequence(SequenceRemapping { triggers, .. }) = remapping
+                && triggers.is_empty()
+            {
+                return Err(miette::miette!(
+                    severity = Severity::Error,
+                    labels = vec![LabeledSpan::new(
+                        Some("empty sequence found here".to_string()),
+                        origin.span.start(),
+                        origin.span.end()
+                    ),],
+                    "empty sequences not allowed"
+                ));
+            }
+            let table = self
+                .conflict_tables
+                .entry(Scope::of(remapping))
+                .or_default();
+            // The remappings of one statement can still conflict with each other
+            if let Some(existing) = table.find_conflict(remapping) {
+                return Err(conflicting_binds(existing.span, origin.span));
+            }
+            table.insert(remapping, origin);
+        }
+        Ok(())
+    }
+}
 
-                    let mut created_new = false;
-                    let mut current_trie = &mut self.sequence_remappings;
-                    for (i, trigger) in triggers.iter().enumerate() {
-                        // Ingore timers
-                        match trigger {
-                            AdvancedTrigger::KeyPress { .. } => (),
-                            AdvancedTrigger::KeyRelease { .. } => (),
-                            _ => continue,
-                        };
-                        let next_node = current_trie.entry(*trigger).or_insert_with(|| {
-                            created_new = true;
-                            SequenceTrie {
-                                next: HashMap::new(),
-                                behavior: *behavior,
-                                span,
-                            }
-                        });
-                        if &next_node.behavior != behavior {
-                            return Err(conflicting_binds(next_node.span, span));
-                        }
+/// Which of two conflicting statements is kept. Statements for single keys win over key sets and
+/// smaller key sets over larger ones, a conflict between statements of the same precedence is an
+/// error.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Precedence {
+    Statement,
+    KeySet(usize),
+}
 
-                        if i == triggers.len() - 1 {
-                            if !created_new {
-                                return Err(conflicting_binds(next_node.span, span));
-                            }
-                        } else {
-                            if !created_new && next_node.next.is_empty() {
-                                return Err(conflicting_binds(next_node.span, span));
-                            }
-                        }
+#[derive(Clone, Copy)]
+struct Origin {
+    span: Span,
+    precedence: Precedence,
+}
 
-                        current_trie = &mut next_node.next;
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct Scope {
+    app: Option<NameMatcher>,
+    toggles: Vec<ToggleCondition>,
+    /// The keys of `held` and `!held` conditions, with true for held
+    held_keys: Vec<(KeyIdent, bool)>,
+}
+impl Scope {
+    fn of(remapping: &Remapping) -> Self {
+        let held_keys = match remapping {
+            Remapping::Basic(_) => Vec::new(),
+            Remapping::Sequence(sequence) => sequence
+                .triggers
+                .iter()
+                .filter_map(|trigger| match trigger {
+                    AdvancedTrigger::KeyHeld { value } => Some((*value, true)),
+                    AdvancedTrigger::KeyNotHeld { value } => Some((*value, false)),
+                    _ => None,
+                })
+                .unique()
+                .collect(),
+        };
+        Scope {
+            app: remapping.app().cloned(),
+            toggles: remapping.toggles().to_vec(),
+            held_keys,
+        }
+    }
+
+    /// Scopes for different apps, opposite values of a toggle or opposite states of a key never
+    /// apply at the same time
+    fn overlaps(&self, other: &Scope) -> bool {
+        let apps = self.app.is_none() || other.app.is_none() || self.app == other.app;
+        let toggles = !self.toggles.iter().any(|a| {
+            other
+                .toggles
+                .iter()
+                .any(|b| a.toggle == b.toggle && a.value != b.value)
+        });
+        let held_keys = !self.held_keys.iter().any(|(a, a_held)| {
+            other
+                .held_keys
+                .iter()
+                .any(|(b, b_held)| a == b && a_held != b_held)
+        });
+        apps && toggles && held_keys
+    }
+}
+
+#[derive(Default)]
+struct ConflictTable {
+    basic_remappings: HashMap<BasicTrigger, Origin>,
+    sequence_remappings: HashMap<AdvancedTrigger, SequenceTrie>,
+}
+impl ConflictTable {
+    fn find_conflict(&self, remapping: &Remapping) -> Option<Origin> {
+        match remapping {
+            Remapping::Basic(basic_remapping) => {
+                self.basic_remappings.get(&basic_remapping.trigger).copied()
+            }
+            Remapping::Sequence(SequenceRemapping {
+                triggers, behavior, ..
+            }) => {
+                let mut current_trie = &self.sequence_remappings;
+                for (i, trigger) in triggers.iter().enumerate() {
+                    // Ignore timers and conditions
+                    if !trigger.is_input() {
+                        continue;
                     }
+                    // Nothing in the table starts with the sequence so far
+                    let next_node = current_trie.get(trigger)?;
+                    if &next_node.behavior != behavior
+                        || i == triggers.len() - 1
+                        || next_node.next.is_empty()
+                    {
+                        return Some(next_node.origin);
+                    }
+                    current_trie = &next_node.next;
+                }
+                None
+            }
+        }
+    }
+
+    fn insert(&mut self, remapping: &Remapping, origin: Origin) {
+        match remapping {
+            Remapping::Basic(basic_remapping) => {
+                self.basic_remappings
+                    .insert(basic_remapping.trigger.clone(), origin);
+            }
+            Remapping::Sequence(SequenceRemapping {
+                triggers, behavior, ..
+            }) => {
+                let mut current_trie = &mut self.sequence_remappings;
+                for trigger in triggers.iter().filter(|trigger| trigger.is_input()) {
+                    current_trie = &mut current_trie
+                        .entry(*trigger)
+                        .or_insert_with(|| SequenceTrie {
+                            next: HashMap::new(),
+                            behavior: *behavior,
+                            origin,
+                        })
+                        .next;
                 }
             }
         }
-        Ok(())
     }
 }
 
 struct SequenceTrie {
     next: HashMap<AdvancedTrigger, SequenceTrie>,
     behavior: Behavior,
-    span: Span,
+    origin: Origin,
 }
 
 impl ast::Layer {
-    fn compile(self, config: &ConfigData, layers: HashMap<String, usize>) -> miette::Result<Layer> {
+    fn compile(
+        self,
+        config: &ConfigData,
+        layers: HashMap<String, usize>,
+        toggles: HashMap<String, usize>,
+        target: TargetOs,
+    ) -> miette::Result<Layer> {
+        let mut config = config.clone();
+        config.resolve_chars()?;
         let mut state = LayerCompilationState {
-            basic_remappings: HashMap::new(),
-            sequence_remappings: HashMap::new(),
-            config: config.clone(),
-            layers: layers.clone(),
+            conflict_tables: HashMap::new(),
+            config,
+            layers,
+            toggles,
+            target,
+            toggle_releases: Vec::new(),
         };
 
+        let (key_sets, statements): (Vec<_>, Vec<_>) = self
+            .statements
+            .into_iter()
+            .partition(|s| matches!(s.lhs.value, ast::Trigger::KeySet(_)));
+
+        let mut remappings = Vec::new();
+        for statement in statements {
+            remappings.extend(state.compile_statement(statement, Precedence::Statement)?);
+        }
+
+        // Key sets are compiled after the statements that win over them, smallest first
+        let key_sets = key_sets
+            .into_iter()
+            .map(|s| match &s.lhs.value {
+                ast::Trigger::KeySet(keys) => (Precedence::KeySet(keys.len()), s),
+                _ => unreachable!("partitioned by trigger"),
+            })
+            .sorted_by_key(|(precedence, _)| *precedence);
+        for (precedence, statement) in key_sets {
+            for key_statement in ast::Statement::expand_key_set(statement) {
+                remappings.extend(state.compile_statement(key_statement, precedence)?);
+            }
+        }
+        remappings.extend(state.toggle_releases.into_iter().map(Remapping::Basic));
+
         Ok(Layer {
             layer_name: self.name.value,
-            remappings: self
-                .statements
-                .into_iter()
-                .map(|statement| {
-                    let span = statement.span;
-                    let remappings = ast::Statement::compile(statement, &mut state);
-                    state
-                        .try_insert_remappings(&remappings, span)
-                        .map(|_| remappings)
-                })
-                .collect::<Result<Vec<_>, _>>()?
-                .into_iter()
-                .flatten()
-                .collect(),
+            indicator: self.indicator.map(|indicator| indicator.value),
+            remappings,
         })
     }
 }
 impl ast::Statement {
+    fn expand_key_set(statement: Spanned<Self>) -> Vec<Spanned<Self>> {
+        let ast::Trigger::KeySet(keys) = &statement.lhs.value else {
+            return vec![statement];
+        };
+
+        let span = statement.lhs.span;
+        keys.iter()
+            .map(|key| {
+                let key = ast::Key::Unspecified(Spanned::new(KeyRef::Pos(*key), span));
+                let mut key_statement = statement.clone();
+                key_statement.lhs = Spanned::new(ast::Trigger::Key(Spanned::new(key, span)), span);
+                key_statement
+            })
+            .collect()
+    }
+
+    /// The prior idle time of a trigger is not compiled here, `compile_statement` adds it in
+    /// front of the triggers together with the conditions of the statement
     fn compile(statment: Spanned<Self>, state: &mut LayerCompilationState) -> Vec<Remapping> {
         match &statment.lhs.value {
+            ast::Trigger::KeySet(_) => unreachable!("key sets are expanded before compiling"),
             ast::Trigger::Key(trigger_key) => {
                 if let Some(result) = ast::Statement::try_key_swap(&statment) {
                     return result;
                 }
 
                 match trigger_key.value {
-                    ast::Key::Unspecified(key) => vec![
-                        Remapping::Basic(BasicRemapping {
-                            trigger: BasicTrigger::KeyPress { value: key.value },
+                    ast::Key::Unspecified(key) => {
+                        let mut remappings = vec![Remapping::Basic(BasicRemapping {
+                            trigger: BasicTrigger::press(key.value.resolved()),
                             binds: ast::Bind::compile(&statment.rhs, state),
-                        }),
-                        Remapping::Basic(BasicRemapping {
-                            trigger: BasicTrigger::KeyRelease { value: key.value },
-                            binds: vec![],
-                        }),
-                    ],
-                    ast::Key::Down(key) => {
-                        vec![Remapping::Basic(BasicRemapping {
-                            trigger: BasicTrigger::KeyPress { value: key.value },
-                            binds: ast::Bind::compile(&statment.rhs, state),
-                        })]
+                            app: None,
+                            toggles: vec![],
+                        })];
+                        remappings.extend(BasicTrigger::release(key.value.resolved()).map(
+                            |trigger| {
+                                Remapping::Basic(BasicRemapping {
+                                    trigger,
+                                    binds: vec![],
+                                    app: None,
+                                    toggles: vec![],
+                                })
+                            },
+                        ));
+                        remappings
                     }
-                    ast::Key::Up(key) => {
+                    ast::Key::Down(key) => {
                         vec![Remapping::Basic(BasicRemapping {
-                            trigger: BasicTrigger::KeyRelease { value: key.value },
+                            trigger: BasicTrigger::press(key.value.resolved()),
                             binds: ast::Bind::compile(&statment.rhs, state),
+                            app: None,
+                            toggles: vec![],
                         })]
                     }
+                    ast::Key::Up(key) => BasicTrigger::release(key.value.resolved())
+                        .map(|trigger| {
+                            Remapping::Basic(BasicRemapping {
+                                trigger,
+                                binds: ast::Bind::compile(&statment.rhs, state),
+                                app: None,
+                                toggles: vec![],
+                            })
+                        })
+                        .into_iter()
+                        .collect(),
                 }
             }
             ast::Trigger::AppFocused(app_name) => {
@@ -296,20 +914,45 @@ impl ast::Statement {
                         app_name: app_name.value.clone(),
                     },
                     binds: ast::Bind::compile(&statment.rhs, state),
+                    app: None,
+                    toggles: vec![],
+                })]
+            }
+            ast::Trigger::AppUnfocused(app_name) => {
+                vec![Remapping::Basic(BasicRemapping {
+                    trigger: BasicTrigger::AppUnfocused {
+                        app_name: app_name.value.clone(),
+                    },
+                    binds: ast::Bind::compile(&statment.rhs, state),
+                    app: None,
+                    toggles: vec![],
                 })]
             }
-            ast::Trigger::Tap(key, behavior, timeout) => {
+            ast::Trigger::WindowTitle(title) => {
+                vec![Remapping::Basic(BasicRemapping {
+                    trigger: BasicTrigger::WindowTitle {
+                        title: title.value.compile(),
+                    },
+                    binds: ast::Bind::compile(&statment.rhs, state),
+                    app: None,
+                    toggles: vec![],
+                })]
+            }
+            ast::Trigger::Tap(key, behavior, timeout, _prior_idle) => {
                 vec![Remapping::Sequence(SequenceRemapping {
-                    triggers: vec![
-                        AdvancedTrigger::KeyPress { value: key.value },
-                        AdvancedTrigger::MaximumWait {
+                    triggers: [
+                        Some(AdvancedTrigger::press(key.value.resolved())),
+                        Some(AdvancedTrigger::MaximumWait {
                             duration: timeout
                                 .as_deref()
                                 .copied()
                                 .unwrap_or(state.config.tap_timeout),
-                        },
-                        AdvancedTrigger::KeyRelease { value: key.value },
-                    ],
+                        }),
+                        AdvancedTrigger::release(key.value.resolved()),
+                    ]
+                    .into_iter()
+                    .flatten()
+                    .collect(),
                     binds: ast::Bind::compile(&statment.rhs, state),
                     behavior: ast::Behavior::compile(
                         behavior
@@ -317,12 +960,14 @@ impl ast::Statement {
                             .copied()
                             .unwrap_or(state.config.default_behavior),
                     ),
+                    app: None,
+                    toggles: vec![],
                 })]
             }
-            ast::Trigger::Hold(key, behavior, timeout) => {
+            ast::Trigger::Hold(key, behavior, timeout, _prior_idle) => {
                 vec![Remapping::Sequence(SequenceRemapping {
                     triggers: vec![
-                        AdvancedTrigger::KeyPress { value: key.value },
+                        AdvancedTrigger::press(key.value.resolved()),
                         AdvancedTrigger::MinimumWait {
                             duration: timeout
                                 .as_deref()
@@ -337,13 +982,15 @@ impl ast::Statement {
                             .copied()
                             .unwrap_or(state.config.default_behavior),
                     ),
+                    app: None,
+                    toggles: vec![],
                 })]
             }
-            ast::Trigger::Chord(keys, behavior, timeout) => {
+            ast::Trigger::Chord(keys, behavior, timeout, _prior_idle) => {
                 vec![Remapping::Sequence(SequenceRemapping {
                     triggers: keys
                         .iter()
-                        .map(|k| AdvancedTrigger::KeyPress { value: k.value })
+                        .map(|k| AdvancedTrigger::press(k.value.resolved()))
                         .intersperse_with(|| AdvancedTrigger::MaximumWait {
                             duration: timeout
                                 .as_deref()
@@ -359,25 +1006,25 @@ impl ast::Statement {
                             .copied()
                             .unwrap_or(state.config.default_behavior),
                     ),
+                    app: None,
+                    toggles: vec![],
                 })]
             }
-            ast::Trigger::Sequence(keys, behavior, timeout) => {
+            ast::Trigger::Sequence(steps, behavior, timeout, _prior_idle) => {
                 vec![Remapping::Sequence(SequenceRemapping {
-                    triggers: keys
-                        .iter()
-                        .flat_map(|k| {
+                    triggers: ast::Step::compile(
+                        steps,
+                        timeout
+                            .as_deref()
+                            .copied()
+                            .unwrap_or(state.config.sequence_timeout),
+                        |key| {
                             [
-                                AdvancedTrigger::KeyPress { value: k.value },
-                                AdvancedTrigger::KeyRelease { value: k.value },
+                                Some(AdvancedTrigger::press(key.resolved())),
+                                AdvancedTrigger::release(key.resolved()),
                             ]
-                        })
-                        .intersperse_with(|| AdvancedTrigger::MaximumWait {
-                            duration: timeout
-                                .as_deref()
-                                .copied()
-                                .unwrap_or(state.config.sequence_timeout),
-                        })
-                        .collect(),
+                        },
+                    ),
 
                     binds: ast::Bind::compile(&statment.rhs, state),
                     behavior: ast::Behavior::compile(
@@ -386,32 +1033,31 @@ impl ast::Statement {
                             .copied()
                             .unwrap_or(state.config.default_behavior),
                     ),
+                    app: None,
+                    toggles: vec![],
                 })]
             }
-            ast::Trigger::Combo(keys, behavior, timeout) => {
+            ast::Trigger::Combo(steps, behavior, timeout, _prior_idle) => {
                 vec![Remapping::Sequence(SequenceRemapping {
-                    triggers: keys
-                        .iter()
-                        .flat_map(|specified_key| match specified_key.value {
+                    triggers: ast::Step::compile(
+                        steps,
+                        timeout
+                            .as_deref()
+                            .copied()
+                            .unwrap_or(state.config.combo_timeout),
+                        |specified_key| match specified_key {
                             ast::Key::Unspecified(key) => [
-                                Some(AdvancedTrigger::KeyPress { value: key.value }),
-                                Some(AdvancedTrigger::KeyRelease { value: key.value }),
+                                Some(AdvancedTrigger::press(key.value.resolved())),
+                                AdvancedTrigger::release(key.value.resolved()),
                             ],
                             ast::Key::Down(key) => {
-                                [Some(AdvancedTrigger::KeyPress { value: key.value }), None]
+                                [Some(AdvancedTrigger::press(key.value.resolved())), None]
                             }
                             ast::Key::Up(key) => {
-                                [Some(AdvancedTrigger::KeyRelease { value: key.value }), None]
+                                [AdvancedTrigger::release(key.value.resolved()), None]
                             }
-                        })
-                        .flatten()
-                        .intersperse_with(|| AdvancedTrigger::MaximumWait {
-                            duration: timeout
-                                .as_deref()
-                                .copied()
-                                .unwrap_or(state.config.combo_timeout),
-                        })
-                        .collect(),
+                        },
+                    ),
 
                     binds: ast::Bind::compile(&statment.rhs, state),
                     behavior: ast::Behavior::compile(
@@ -420,6 +1066,8 @@ impl ast::Statement {
                             .copied()
                             .unwrap_or(state.config.default_behavior),
                     ),
+                    app: None,
+                    toggles: vec![],
                 })]
             }
         }
@@ -429,27 +1077,30 @@ impl ast::Statement {
         if let ast::Trigger::Key(trigger_key) = statment.lhs.value
             && statment.rhs.len() == 1
             && let ast::Bind::Key(bind_key) = statment.rhs[0].value
-            && let (Some(trigger_key_ident), Some(bind_key_ident)) = (
+            && let (Some(trigger_key_ref), Some(bind_key_ref)) = (
                 trigger_key.value.is_basic_key(),
                 bind_key.value.is_basic_key(),
             )
+            && let (trigger_key_ident, bind_key_ident) =
+                (trigger_key_ref.resolved(), bind_key_ref.resolved())
+            // Wheel events have no release to carry the other half of the swap
+            && let (Some(release_trigger), Some(release_bind)) = (
+                BasicTrigger::release(trigger_key_ident),
+                Bind::release(bind_key_ident),
+            )
         {
             let remappings = vec![
                 Remapping::Basic(BasicRemapping {
-                    trigger: BasicTrigger::KeyPress {
-                        value: trigger_key_ident,
-                    },
-                    binds: vec![Bind::PressKey {
-                        value: bind_key_ident,
-                    }],
+                    trigger: BasicTrigger::press(trigger_key_ident),
+                    binds: vec![Bind::press(bind_key_ident)],
+                    app: None,
+                    toggles: vec![],
                 }),
                 Remapping::Basic(BasicRemapping {
-                    trigger: BasicTrigger::KeyRelease {
-                        value: trigger_key_ident,
-                    },
-                    binds: vec![Bind::ReleaseKey {
-                        value: bind_key_ident,
-                    }],
+                    trigger: release_trigger,
+                    binds: vec![release_bind],
+                    app: None,
+                    toggles: vec![],
                 }),
             ];
             return Some(remappings);
@@ -457,6 +1108,122 @@ impl ast::Statement {
         None
     }
 }
+impl ast::NameMatcher {
+    fn compile(&self) -> NameMatcher {
+        match self {
+            ast::NameMatcher::Exact(value) => NameMatcher::Exact {
+                value: value.clone(),
+            },
+            ast::NameMatcher::Glob(pattern) => NameMatcher::Glob {
+                pattern: pattern.clone(),
+            },
+            ast::NameMatcher::Regex(pattern) => NameMatcher::Regex {
+                pattern: pattern.clone(),
+            },
+        }
+    }
+}
+impl ast::Trigger {
+    /// The time without key presses that must pass before the trigger starts, in milliseconds.
+    /// Holds and chords fall back to the config since they misfire while typing fast. Taps are
+    /// what typing produces anyway, so they only wait for the idle time when the option is given.
+    fn prior_idle(&self, config: &ConfigData) -> Option<usize> {
+        let duration = match self {
+            ast::Trigger::Hold(.., prior_idle) | ast::Trigger::Chord(.., prior_idle) => prior_idle
+                .as_ref()
+                .map(|d| d.as_millis() as usize)
+                .unwrap_or(config.require_prior_idle),
+            ast::Trigger::Tap(.., prior_idle)
+            | ast::Trigger::Sequence(.., prior_idle)
+            | ast::Trigger::Combo(.., prior_idle) => {
+                prior_idle.as_ref().map(|d| d.as_millis() as usize)?
+            }
+            _ => return None,
+        };
+        (duration > 0).then_some(duration)
+    }
+}
+impl<K> ast::Step<K> {
+    /// Joins the events of the keys, waiting at most `timeout` between events. A `wait` step
+    /// delays the timeout by its duration and a `within` step replaces it for that gap
+    fn compile(
+        steps: &[Spanned<Self>],
+        timeout: usize,
+        events: impl Fn(&K) -> [Option<AdvancedTrigger>; 2],
+    ) -> Vec<AdvancedTrigger> {
+        let mut triggers = Vec::new();
+        let mut timers = Vec::new();
+        for step in steps {
+            match &step.value {
+                ast::Step::Key(key) => {
+                    for event in events(key).into_iter().flatten() {
+                        let has_maximum = timers
+                            .iter()
+                            .any(|timer| matches!(timer, AdvancedTrigger::MaximumWait { .. }));
+                        if !triggers.is_empty() && !has_maximum {
+                            let waited: usize = timers
+                                .iter()
+                                .map(|timer| match timer {
+                                    AdvancedTrigger::MinimumWait { duration } => *duration,
+                                    _ => 0,
+                                })
+                                .sum();
+                            timers.push(AdvancedTrigger::MaximumWait {
+                                duration: waited + timeout,
+                            });
+                        }
+                        triggers.append(&mut timers);
+                        triggers.push(event);
+                    }
+                }
+                ast::Step::Wait(duration) => timers.push(AdvancedTrigger::MinimumWait {
+                    duration: duration.as_millis() as usize,
+                }),
+                ast::Step::Within(duration) => timers.push(AdvancedTrigger::MaximumWait {
+                    duration: duration.as_millis() as usize,
+                }),
+            }
+        }
+        triggers.append(&mut timers);
+        triggers
+    }
+}
+impl ast::Condition {
+    /// Toggle conditions are not part of the triggers, they are compiled by `compile_toggle`
+    fn compile(&self) -> Option<AdvancedTrigger> {
+        match self {
+            ast::Condition::Held(key) => Some(AdvancedTrigger::KeyHeld {
+                value: key.value.resolved(),
+            }),
+            ast::Condition::NotHeld(key) => Some(AdvancedTrigger::KeyNotHeld {
+                value: key.value.resolved(),
+            }),
+            ast::Condition::ToggleOn(_) | ast::Condition::ToggleOff(_) => None,
+        }
+    }
+
+    fn compile_toggle(&self, toggles: &HashMap<String, usize>) -> Option<ToggleCondition> {
+        let (name, value) = match self {
+            ast::Condition::ToggleOn(name) => (name, true),
+            ast::Condition::ToggleOff(name) => (name, false),
+            ast::Condition::Held(_) | ast::Condition::NotHeld(_) => return None,
+        };
+        Some(ToggleCondition {
+            toggle: *toggles
+                .get(&name.value)
+                .expect("toggle must exist after checking the profile"),
+            value,
+        })
+    }
+}
+impl KeyRef {
+    fn resolved(self) -> KeyIdent {
+        match self {
+            KeyRef::Pos(key) => key,
+            KeyRef::Char(_) => unreachable!("characters are resolved before compiling"),
+        }
+    }
+}
 impl ast::Behavior {
     fn compile(self) -> Behavior {
         match self {
@@ -473,22 +1240,14 @@ impl ast::Bind {
             match &bind.value {
                 ast::Bind::Key(key) => match key.value {
                     ast::Key::Unspecified(key_ident) => {
-                        result_binds.push(Bind::PressKey {
-                            value: key_ident.value,
-                        });
-                        result_binds.push(Bind::ReleaseKey {
-                            value: key_ident.value,
-                        });
+                        result_binds.push(Bind::press(key_ident.value.resolved()));
+                        result_binds.extend(Bind::release(key_ident.value.resolved()));
                     }
                     ast::Key::Down(key_ident) => {
-                        result_binds.push(Bind::PressKey {
-                            value: key_ident.value,
-                        });
+                        result_binds.push(Bind::press(key_ident.value.resolved()));
                     }
                     ast::Key::Up(key_ident) => {
-                        result_binds.push(Bind::ReleaseKey {
-                            value: key_ident.value,
-                        });
+                        result_binds.extend(Bind::release(key_ident.value.resolved()));
                     }
                 },
                 ast::Bind::None => (),
@@ -505,23 +1264,114 @@ impl ast::Bind {
                     interpreter: interpreter.value.clone(),
                     script: script.value.clone(),
                 }),
+                ast::Bind::Exec { argv, options } => {
+                    let mut env = BTreeMap::new();
+                    let mut cwd = None;
+                    let mut timeout = None;
+                    let mut detach = false;
+                    for option in options.iter() {
+                        match &option.value {
+                            ast::ExecOption::Cwd(dir) => cwd = Some(dir.value.clone()),
+                            ast::ExecOption::Env(vars) => env.extend(vars.iter().map(|var| {
+                                let ast::EnvVar { name, value } = &var.value;
+                                (name.value.clone(), value.value.clone())
+                            })),
+                            ast::ExecOption::Timeout(duration) => {
+                                timeout = Some(duration.as_millis() as usize)
+                            }
+                            ast::ExecOption::Detach(value) => detach = value.value,
+                        }
+                    }
+                    result_binds.push(Bind::Exec {
+                        argv: argv.iter().map(|arg| arg.value.clone()).collect(),
+                        env,
+                        cwd,
+                        timeout,
+                        detach,
+                    });
+                }
                 ast::Bind::OpenApp(Spanned { value: name, .. }) => {
-                    #[cfg(target_os = "macos")]
-                    let (interpreter, script) =
-                        ("sh".to_string(), format!(r#"open -a "{}""#, name));
-
-                    #[cfg(target_os = "linux")]
-                    let (interpreter, script) = ("sh".to_string(), name.clone());
-
-                    #[cfg(target_os = "windows")]
-                    let (interpreter, script) =
-                        ("cmd.exe".to_string(), format!(r#"start "" "{}""#, name));
+                    let (interpreter, script) = match state.target {
+                        // The name is quoted, so it can only ever start an app and not run
+                        // other commands, which is why open_app does not need advanced mode
+                        TargetOs::Macos => {
+                            ("sh".to_string(), format!("open -a {}", shell_quote(name)))
+                        }
+                        // Desktop entries can only be looked up on the machine itself
+                        TargetOs::Linux => {
+                            let entry = match TargetOs::host() {
+                                TargetOs::Linux => desktop_entry::find(name),
+                                _ => None,
+                            };
+                            let script = entry
+                                .map(|entry| entry.launch_command())
+                                .unwrap_or_else(|| format!("gtk-launch {}", shell_quote(name)));
+                            ("sh".to_string(), script)
+                        }
+                        // The checker rejects names with characters cmd.exe expands in quotes
+                        TargetOs::Windows => {
+                            ("cmd.exe".to_string(), format!(r#"start "" "{}""#, name))
+                        }
+                    };
 
                     result_binds.push(Bind::RunScript {
                         interpreter,
                         script,
                     });
                 }
+                ast::Bind::MouseMove { dx, dy } => result_binds.push(Bind::MoveMouse {
+                    dx: dx.value,
+                    dy: dy.value,
+                }),
+                ast::Bind::Notify(message) => result_binds.push(Bind::Notify {
+                    message: message.value.clone(),
+                }),
+                ast::Bind::SetToggle(name, value) => result_binds.push(Bind::SetToggle {
+                    toggle: *state
+                        .toggles
+                        .get(&name.value)
+                        .expect("toggle must exist after checking the profile"),
+                    value: value.value,
+                }),
+                ast::Bind::FlipToggle(name) => result_binds.push(Bind::FlipToggle {
+                    toggle: *state
+                        .toggles
+                        .get(&name.value)
+                        .expect("toggle must exist after checking the profile"),
+                }),
+                ast::Bind::Symbol { modifier, key } => {
+                    result_binds.push(Bind::press(*modifier));
+                    result_binds.push(Bind::press(*key));
+                    result_binds.extend(Bind::release(*key));
+                    result_binds.extend(Bind::release(*modifier));
+                }
+                ast::Bind::Unicode(text) => result_binds.push(Bind::TypeUnicode {
+                    value: text.value.clone(),
+                    method: UnicodeInputMethod::for_target(state.target),
+                }),
+                ast::Bind::CapsWord => {
+                    let layout = state.config.keyboard_layout;
+                    let (shifted_keys, continue_keys) = state
+                        .config
+                        .caps_word_keys
+                        .iter()
+                        .map(|k| k.value.resolved())
+                        .unique()
+                        .partition(|k| {
+                            let shifted = layout.char_for_key(Some(KeyIdent::ShiftLeft), *k);
+                            match layout.char_for_key(None, *k) {
+                                Some('-') => shifted == Some('_'),
+                                Some(c) => {
+                                    c.is_lowercase() && shifted.is_some_and(char::is_uppercase)
+                                }
+                                None => false,
+                            }
+                        });
+                    result_binds.push(Bind::CapsWord {
+                        shifted_keys,
+                        continue_keys,
+                    });
+                }
             }
         }
         result_binds
@@ -541,6 +1391,8 @@ fn conflicting_binds(fst: Span, snd: Span) -> miette::Report {
 
 #[rustfmt::skip]
 impl KeyIdent {
+    /// The name of the key in the compiled profile. The key binder maps these names to the key
+    /// codes of the platform it runs on, so they are the same for every target.
     pub fn to_keybinder_key(&self) -> &'static str {
         use KeyIdent::*;
         match self {
@@ -583,6 +1435,8 @@ impl KeyIdent {
             Backspace => "Backspace", Space => "Space",
             Insert => "Insert", Delete => "Delete", Home => "Home", End => "End",
             PageUp => "PageUp", PageDown => "PageDown",
+            PrintScreen => "PrintScreen", ScrollLock => "ScrollLock", Pause => "Pause",
+            Menu => "Menu",
 
             // Arrows
             Up => "ArrowUp", Down => "ArrowDown", Left => "ArrowLeft", Right => "ArrowRight",
@@ -591,6 +1445,15 @@ impl KeyIdent {
             Minus => "Minus", Equals => "Equals", LeftBracket => "LeftBracket", RightBracket => "RightBracket",
             Backslash => "Backslash", Semicolon => "Semicolon", Quote => "Apostrophe", Comma => "Comma",
             Period => "Period", Slash => "Slash", Grave => "Grave",
+            IntlBackslash => "IntlBackslash",
+
+            // Mouse buttons
+            Mouse1 => "MouseLeft", Mouse2 => "MouseRight", Mouse3 => "MouseMiddle",
+            Mouse4 => "MouseBack", Mouse5 => "MouseForward",
+
+            // Mouse wheel
+            WheelUp => "WheelUp", WheelDown => "WheelDown",
+            WheelLeft => "WheelLeft", WheelRight => "WheelRight",
         }
     }
 }
@@ -600,5 +1463,908 @@ where
     S: Serializer,
 {
     let s = key.to_keybinder_key();
-    serializer.serialize_str(&s)
+    serializer.serialize_str(s)
+}
+
+pub fn serialize_keys<S>(keys: &[KeyIdent], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.collect_seq(keys.iter().map(|k| k.to_keybinder_key()))
+}
+
+#[cfg(test)]
+mod compile_tests {
+    use super::*;
+    use crate::{
+        lex::Lexer,
+        parse::{Parse, TokenStream},
+    };
+    use serde_json::{json, Value};
+
+    fn parse(source: &str) -> ast::Profile {
+        let mut ts = TokenStream::new(Lexer::new("test", source));
+        ast::Profile::parse(&mut ts)
+            .unwrap_or_else(|err| panic!("{:?}", err.with_source_code(source.to_string())))
+    }
+
+    fn compile_for(source: &str, target: TargetOs) -> Value {
+        let profile = parse(source);
+        let errors: Vec<_> = profile
+            .check(target)
+            .into_iter()
+            .filter(|r| r.severity() == Some(Severity::Error))
+            .collect();
+        assert!(errors.is_empty(), "{errors:?}");
+        let compiled =
+            profile
+                .for_target(target)
+                .compile(target, source, &ParamOverrides::default());
+        serde_json::to_value(compiled.unwrap()).unwrap()
+    }
+
+    fn compile(source: &str) -> Value {
+        compile_for(source, TargetOs::Linux)
+    }
+
+    fn compile_report(source: &str) -> miette::Report {
+        let profile = parse(source).for_target(TargetOs::Linux);
+        profile
+            .compile(TargetOs::Linux, source, &ParamOverrides::default())
+            .unwrap_err()
+    }
+
+    fn compile_error(source: &str) -> String {
+        compile_report(source).to_string()
+    }
+
+    fn key_presses(profile: &Value) -> HashMap<&str, &str> {
+        remappings(profile)
+            .iter()
+            .filter(|r| r["trigger"]["type"] == "key_press")
+            .map(|r| {
+                let key = r["trigger"]["value"].as_str().unwrap();
+                (key, r["binds"][0]["value"].as_str().unwrap())
+            })
+            .collect()
+    }
+
+    fn remappings(profile: &Value) -> &Vec<Value> {
+        profile["layers"][0]["remappings"].as_array().unwrap()
+    }
+
+    #[test]
+    fn test_source_hash() {
+        let source = "profile \"Test\"\nconfig {\n}\nlayer \"base\" {\n}\n";
+        let hash = &compile(source)["metadata"]["source_hash"];
+        assert_eq!(hash, &format!("{:016x}", fnv1a_hash(source.as_bytes())));
+        assert_ne!(
+            hash,
+            &compile(&format!("{source}\n"))["metadata"]["source_hash"]
+        );
+
+        let params: ParamOverrides = [("a".to_string(), "1".to_string())].into_iter().collect();
+        assert_ne!(
+            source_hash(source, &params),
+            source_hash(source, &ParamOverrides::default())
+        );
+    }
+
+    #[test]
+    fn test_key_set_precedence() {
+        let profile = compile(
+            r#"profile "Test"
+config {
+}
+layer "base" {
+    [a-z] = y
+    a = z
+    [a-c] = x
+}
+"#,
+        );
+        let presses = key_presses(&profile);
+        assert_eq!(presses.len(), 26);
+        assert_eq!(presses["A"], "Z");
+        assert_eq!(presses["B"], "X");
+        assert_eq!(presses["C"], "X");
+        assert_eq!(presses["D"], "Y");
+    }
+
+    #[test]
+    fn test_key_set_conflicts() {
+        let profile = |key_sets: &str| {
+            format!("profile \"Test\"\nconfig {{\n}}\nlayer \"base\" {{\n{key_sets}\n}}\n")
+        };
+        assert_eq!(
+            compile_error(&profile("[a-c] = x\n[a-c] = y")),
+            "Conflicting statments"
+        );
+        assert_eq!(
+            compile_error(&profile("[a-c] = x\n[c-e] = y")),
+            "Conflicting statments"
+        );
+        assert_eq!(
+            compile_error(&profile("a = x\n[a-c] = run([\"echo\"])")),
+            "run bind requires advanced mode"
+        );
+    }
+
+    #[test]
+    fn test_swap() {
+        let source = r#"profile "Test"
+config {
+}
+layer "base" {
+    capslock <-> esc
+    swap(a, b)
+}
+"#;
+        let profile = compile(source);
+        assert_eq!(
+            key_presses(&profile),
+            HashMap::from([
+                ("CapsLock", "Escape"),
+                ("Escape", "CapsLock"),
+                ("A", "B"),
+                ("B", "A"),
+            ])
+        );
+        assert_eq!(remappings(&profile).len(), 8);
+
+        let conflict = source.replace("swap(a, b)", "swap(a, b)\n    b = c");
+        let report = compile_report(&conflict);
+        let offsets: Vec<_> = report.labels().unwrap().map(|l| l.offset()).collect();
+        assert_eq!(
+            offsets,
+            [
+                conflict.find("swap").unwrap(),
+                conflict.find("b = c").unwrap()
+            ]
+        );
+
+        let profile = compile(&source.replace("capslock <-> esc", "pos(q) <-> x\n    '1' <-> esc"));
+        assert_eq!(
+            key_presses(&profile),
+            HashMap::from([
+                ("Q", "X"),
+                ("X", "Q"),
+                ("1", "Escape"),
+                ("Escape", "1"),
+                ("A", "B"),
+                ("B", "A"),
+            ])
+        );
+
+        let invalid = source.replace("capslock <-> esc", "_a <-> b");
+        let mut ts = TokenStream::new(Lexer::new("test", &invalid));
+        let error = ast::Profile::parse(&mut ts).unwrap_err();
+        assert_eq!(error.to_string(), "Invalid left side of `<->`");
+    }
+
+    #[test]
+    fn test_grid() {
+        let profile = compile(
+            r#"profile "Test"
+config {
+}
+layer "base" {
+    grid "ansi60" {
+        _ _ _ _ _ _ _ _ _ _ _ _ _ _
+        _ _ x _ _ _ _ _ _ _ _ _ _ _
+        _ _ _ _ _ _ left down up right _ _ _
+        _ _ _ _ _ _ _ _ _ _ _ _
+        _ _ _ _ _ _ _ ctrlright
+    }
+}
+"#,
+        );
+        assert_eq!(
+            key_presses(&profile),
+            HashMap::from([
+                ("W", "X"),
+                ("H", "ArrowLeft"),
+                ("J", "ArrowDown"),
+                ("K", "ArrowUp"),
+                ("L", "ArrowRight"),
+            ])
+        );
+
+        let profile = compile(
+            r#"profile "Test"
+config {
+}
+layer "base" {
+    grid "iso60" {
+        _ _ _ _ _ _ _ _ _ _ _ _ _ _
+        _ _ _ _ _ _ _ _ _ _ _ _ _
+        _ _ _ _ _ _ _ _ _ _ _ _ _ x
+        _ y _ _ _ _ _ _ _ _ _ _ _
+        _ _ _ _ _ _ _ _
+    }
+}
+"#,
+        );
+        assert_eq!(
+            key_presses(&profile),
+            HashMap::from([("Enter", "X"), ("IntlBackslash", "Y")])
+        );
+    }
+
+    #[test]
+    fn test_grid_must_match_the_layout() {
+        let source = r#"profile "Test"
+config {
+}
+layer "base" {
+    grid "ansi60" {
+        _ _ _
+    }
+}
+"#;
+        let parse_error = |source: &str| {
+            let mut ts = TokenStream::new(Lexer::new("test", source));
+            ast::Profile::parse(&mut ts).unwrap_err().to_string()
+        };
+        assert_eq!(parse_error(source), "Grid does not match the ansi60 layout");
+        assert_eq!(
+            parse_error(&source.replace("_ _ _", "_a _ _")),
+            "Grid cells must be separated by spaces"
+        );
+    }
+
+    #[test]
+    fn test_held_conditions() {
+        let source = r#"profile "Test"
+config {
+}
+layer "base" {
+    tap(a) if held(shiftleft) = b
+    tap(a) if !held(shiftleft) = c
+}
+"#;
+        let profile = compile(source);
+        let conditions: Vec<_> = remappings(&profile)
+            .iter()
+            .map(|r| r["triggers"][0].clone())
+            .collect();
+        assert_eq!(
+            conditions,
+            [
+                json!({"type": "key_held", "value": "LeftShift"}),
+                json!({"type": "key_not_held", "value": "LeftShift"}),
+            ]
+        );
+
+        let both = source.replace(
+            "    tap(a) if !held(shiftleft) = c\n",
+            "    tap(a) if !held(shiftleft) = c\n    tap(a) if held(ctrlleft) = d\n",
+        );
+        assert_eq!(compile_error(&both), "Conflicting statments");
+    }
+
+    #[test]
+    fn test_prior_idle() {
+        let source = r#"profile "Test"
+config {
+    require_prior_idle = 100ms
+}
+layer "base" {
+    hold(f) = ctrlleft
+    tap(g) = x
+    chord([j, k], require_prior_idle = 0ms) = esc
+    sequence([a, b], require_prior_idle = 50ms) if held(shiftleft) = c
+    tap(h, require_prior_idle = 2s) = y
+}
+"#;
+        let profile = compile(source);
+        let first_triggers: Vec<_> = remappings(&profile)
+            .iter()
+            .map(|r| r["triggers"][0]["type"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            first_triggers,
+            [
+                "prior_idle",
+                "key_press",
+                "key_press",
+                "prior_idle",
+                "prior_idle"
+            ]
+        );
+        let sequence = &remappings(&profile)[3]["triggers"];
+        assert_eq!(sequence[0]["duration"], 50);
+        assert_eq!(sequence[1]["type"], "key_held");
+        assert_eq!(sequence[2], json!({"type": "key_press", "value": "A"}));
+        assert_eq!(remappings(&profile)[0]["triggers"][0]["duration"], 100);
+
+        let parse_error = |source: &str| {
+            let mut ts = TokenStream::new(Lexer::new("test", source));
+            ast::Profile::parse(&mut ts).unwrap_err().to_string()
+        };
+        let unknown = source.replace("require_prior_idle = 2s", "prior_idle = 2s");
+        assert_eq!(parse_error(&unknown), "Unexpected trigger option");
+        let twice = source.replace("= 2s", "= 2s, require_prior_idle = 1s");
+        assert_eq!(parse_error(&twice), "Duplicate trigger option");
+    }
+
+    #[test]
+    fn test_step_timers() {
+        let profile = compile(
+            r#"profile "Test"
+config {
+    sequence_timeout = 200
+    combo_timeout = 200
+    advanced = true
+}
+layer "base" {
+    sequence([a, wait(300ms), b, within(100ms), c]) = x
+    combo([_e, wait(300ms), ^e, within(50ms), wait(10ms), f]) = y
+}
+"#,
+        );
+        let timers = |index: usize| -> Vec<_> {
+            remappings(&profile)[index]["triggers"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|t| match &t["duration"] {
+                    Value::Null => t["type"].as_str().unwrap().to_string(),
+                    duration => format!("{} {duration}", t["type"].as_str().unwrap()),
+                })
+                .collect()
+        };
+        assert_eq!(
+            timers(0),
+            [
+                "key_press",
+                "maximum_wait 200",
+                "key_release",
+                "minimum_wait 300",
+                "maximum_wait 500",
+                "key_press",
+                "maximum_wait 200",
+                "key_release",
+                "maximum_wait 100",
+                "key_press",
+                "maximum_wait 200",
+                "key_release",
+            ]
+        );
+        assert_eq!(
+            timers(1),
+            [
+                "key_press",
+                "minimum_wait 300",
+                "maximum_wait 500",
+                "key_release",
+                "maximum_wait 50",
+                "minimum_wait 10",
+                "key_press",
+                "maximum_wait 200",
+                "key_release",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_toggles() {
+        let source = r#"profile "Test"
+config {
+}
+toggle gaming
+toggle arrows = true
+layer "base" {
+    f12 = flip(gaming)
+    f11 = set(arrows, false)
+    a if gaming = left
+    a if !gaming = b
+    if arrows {
+        tap(j) = down
+    }
+}
+"#;
+        let profile = compile(source);
+        assert_eq!(
+            profile["toggles"],
+            json!([
+                {"toggle_name": "gaming", "initial": false},
+                {"toggle_name": "arrows", "initial": true},
+            ])
+        );
+        let remappings = remappings(&profile);
+        assert_eq!(
+            remappings[0]["binds"],
+            json!([{"type": "flip_toggle", "toggle": 0}])
+        );
+        assert_eq!(
+            remappings[2]["binds"],
+            json!([{"type": "set_toggle", "toggle": 1, "value": false}])
+        );
+        let toggles = |index: usize| &remappings[index]["toggles"];
+        assert_eq!(toggles(4), &json!([{"toggle": 0, "value": true}]));
+        assert_eq!(toggles(5), &json!([{"toggle": 0, "value": false}]));
+        assert_eq!(toggles(6), &json!([{"toggle": 1, "value": true}]));
+        let release = remappings.last().unwrap();
+        assert_eq!(release["trigger"]["type"], "key_release");
+        assert_eq!(release["trigger"]["value"], "A");
+        assert_eq!(release.get("toggles"), None);
+        assert_eq!(
+            release["binds"],
+            json!([
+                {"type": "release_key", "value": "A"},
+                {"type": "release_key", "value": "ArrowLeft"},
+                {"type": "release_key", "value": "B"},
+            ])
+        );
+
+        let parse_error = |source: &str| {
+            let mut ts = TokenStream::new(Lexer::new("test", source));
+            ast::Profile::parse(&mut ts).unwrap_err().to_string()
+        };
+        let keyword = source.replace("toggle gaming\n", "toggle set\n");
+        assert_eq!(parse_error(&keyword), "Unexpected token found");
+        let value = source.replace("= true", "= 1");
+        assert_eq!(parse_error(&value), "Unexpected token found");
+        let conflict = source.replace("a if !gaming = b", "a if arrows = b");
+        assert_eq!(compile_error(&conflict), "Conflicting statments");
+    }
+
+    #[test]
+    fn test_caps_word_keys() {
+        let profile = compile(
+            r#"profile "Test"
+config {
+    caps_word_keys = [a, "-", 1, backspace]
+}
+layer "base" {
+    capslock = caps_word
+}
+"#,
+        );
+        assert_eq!(
+            remappings(&profile)[0]["binds"],
+            json!([{
+                "type": "caps_word",
+                "shifted_keys": ["A", "Minus"],
+                "continue_keys": ["1", "Backspace"],
+            }])
+        );
+
+        let profile = compile(
+            r#"profile "Test"
+config {
+    keyboard_layout = "azerty"
+}
+layer "base" {
+    capslock = caps_word
+}
+"#,
+        );
+        assert_eq!(
+            remappings(&profile)[0]["binds"],
+            json!([{
+                "type": "caps_word",
+                "shifted_keys": [
+                    "Q", "B", "C", "D", "E", "F", "G", "H", "I", "J", "K", "L", "Semicolon",
+                    "N", "O", "P", "A", "R", "S", "T", "U", "V", "Z", "X", "Y", "W",
+                ],
+                "continue_keys": ["6", "0", "1", "2", "3", "4", "5", "7", "8", "9", "Backspace"],
+            }])
+        );
+    }
+
+    #[test]
+    fn test_character_keys() {
+        let profile = compile(
+            r#"profile "Test"
+config {
+    caps_word_keys = ['q']
+    keyboard_layout = "azerty"
+}
+layer "base" {
+    'q' = esc
+    capslock = caps_word
+    b = '!'
+}
+layer "de" {
+    config {
+        keyboard_layout = "qwertz"
+    }
+    'z' = esc
+    capslock = caps_word
+}
+"#,
+        );
+        let triggers = |layer: usize| -> Vec<_> {
+            profile["layers"][layer]["remappings"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|r| (r["trigger"]["value"].clone(), r["binds"][0].clone()))
+                .step_by(2)
+                .collect()
+        };
+        let caps_word =
+            |key| json!({"type": "caps_word", "shifted_keys": [key], "continue_keys": []});
+        assert_eq!(
+            triggers(0),
+            [
+                (json!("A"), json!({"type": "press_key", "value": "Escape"})),
+                (json!("CapsLock"), caps_word("A")),
+                (json!("B"), json!({"type": "press_key", "value": "Slash"})),
+            ]
+        );
+        assert_eq!(
+            triggers(1),
+            [
+                (json!("Y"), json!({"type": "press_key", "value": "Escape"})),
+                (json!("CapsLock"), caps_word("Q")),
+            ]
+        );
+
+        let profile = compile(
+            r#"profile "Test"
+config {
+}
+layer "base" {
+    a = '!'
+}
+"#,
+        );
+        let binds: Vec<_> = remappings(&profile)[0]["binds"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|b| b["value"].as_str().unwrap())
+            .collect();
+        assert_eq!(binds, ["LeftShift", "1", "1", "LeftShift"]);
+
+        let dead_key = r#"profile "Test"
+config {
+    keyboard_layout = "qwertz"
+}
+layer "base" {
+    a = '^'
+}
+"#;
+        assert_eq!(compile_error(dead_key), "Character cannot be typed");
+        let held = dead_key.replace("a = '^'", "tap('^') = a");
+        assert_eq!(
+            compile_error(&held),
+            "Character not found in the keyboard layout"
+        );
+    }
+
+    #[test]
+    fn test_double_quoted_keys_are_positional() {
+        let source = r#"profile "Test"
+config {
+    keyboard_layout = "azerty"
+}
+layer "base" {
+    a = "-"
+    b = '-'
+    "-" = '1'
+}
+"#;
+        assert_eq!(
+            key_presses(&compile(source)),
+            HashMap::from([("A", "Minus"), ("B", "6"), ("Minus", "LeftShift")])
+        );
+
+        let symbols = source.replace(r#"a = "-""#, r#"a = "!""#);
+        assert_eq!(
+            compile(&symbols)["layers"],
+            compile(&source.replace(r#"a = "-""#, "a = '!'"))["layers"]
+        );
+        assert_eq!(key_presses(&compile(&symbols))["A"], "Slash");
+
+        let source = source.replace(r#""-" = '1'"#, r#""!" = '1'"#);
+        let mut ts = TokenStream::new(Lexer::new("test", &source));
+        let report = ast::Profile::parse(&mut ts).unwrap_err();
+        assert_eq!(report.to_string(), "Unexpected key found");
+        assert_eq!(
+            report.help().unwrap().to_string(),
+            "Use '!' for the key that types the character"
+        );
+    }
+
+    #[test]
+    fn test_missing_default_layer() {
+        let source = r#"profile "Test"
+config {
+    on linux {
+        default_layer = "missing"
+    }
+}
+layer "base" {
+}
+"#;
+        assert_eq!(
+            compile_error(source),
+            "default_layer 'missing' does not exist in profile layers"
+        );
+    }
+
+    #[test]
+    fn test_advanced_is_required() {
+        let source = r#"profile "Test"
+config {
+}
+layer "base" {
+    combo([a, b]) = c
+}
+"#;
+        assert_eq!(
+            compile_error(source),
+            "combo trigger requires advanced mode"
+        );
+        let advanced = source.replace("config {", "config {\n    advanced = true");
+        assert_eq!(remappings(&compile(&advanced)).len(), 1);
+    }
+
+    #[test]
+    fn test_indicator() {
+        let source = r#"profile "Test"
+config {
+}
+layer "base" {
+    indicator = """
+FN"""
+    a = b
+}
+"#;
+        let profile = compile(source);
+        assert_eq!(profile["layers"][0]["indicator"], "FN");
+        assert_eq!(remappings(&profile).len(), 2);
+
+        let parse_error = |source: &str| {
+            let mut ts = TokenStream::new(Lexer::new("test", source));
+            ast::Profile::parse(&mut ts).unwrap_err().to_string()
+        };
+        let twice = source.replace("    a = b", "    indicator = \"F\"");
+        assert_eq!(parse_error(&twice), "A layer can only have one indicator");
+        let nested = source.replace(
+            "    a = b",
+            "    if held(a) {\n        indicator = \"F\"\n    }",
+        );
+        assert_eq!(
+            parse_error(&nested),
+            "Layer indicators cannot be placed inside `when`, `on` or `if` blocks"
+        );
+        let number = source.replace("\"\"\"\nFN\"\"\"", "1");
+        assert_eq!(parse_error(&number), "Unexpected token found");
+    }
+
+    #[test]
+    fn test_mouse() {
+        let source = r#"profile "Test"
+config {
+}
+layer "base" {
+    mouse4 = mouse3
+    wheelup = pageup
+    f1 = wheeldown
+    f2 = mouse_move(10, -5)
+}
+"#;
+        assert_eq!(
+            compile(source)["layers"][0]["remappings"],
+            json!([
+                {
+                    "trigger": {"type": "mouse_button_press", "button": "MouseBack"},
+                    "binds": [{"type": "press_mouse_button", "button": "MouseMiddle"}],
+                },
+                {
+                    "trigger": {"type": "mouse_button_release", "button": "MouseBack"},
+                    "binds": [{"type": "release_mouse_button", "button": "MouseMiddle"}],
+                },
+                {
+                    "trigger": {"type": "wheel", "direction": "WheelUp"},
+                    "binds": [
+                        {"type": "press_key", "value": "PageUp"},
+                        {"type": "release_key", "value": "PageUp"},
+                    ],
+                },
+                {
+                    "trigger": {"type": "key_press", "value": "F1"},
+                    "binds": [{"type": "scroll", "direction": "WheelDown"}],
+                },
+                {"trigger": {"type": "key_release", "value": "F1"}, "binds": []},
+                {
+                    "trigger": {"type": "key_press", "value": "F2"},
+                    "binds": [{"type": "move_mouse", "dx": 10, "dy": -5}],
+                },
+                {"trigger": {"type": "key_release", "value": "F2"}, "binds": []},
+            ])
+        );
+
+        let parse_error = |source: &str| {
+            let mut ts = TokenStream::new(Lexer::new("test", source));
+            ast::Profile::parse(&mut ts).unwrap_err().to_string()
+        };
+        let one_axis = source.replace("mouse_move(10, -5)", "mouse_move(10)");
+        assert_eq!(parse_error(&one_axis), "Unexpected token found");
+    }
+
+    #[test]
+    fn test_unicode() {
+        let source = r#"profile "Test"
+config {
+}
+layer "base" {
+    a = unicode("→")
+    b = char("é")
+}
+"#;
+        let methods = [
+            (TargetOs::Linux, "ctrl_shift_u"),
+            (TargetOs::Macos, "hex_input"),
+            (TargetOs::Windows, "send_input"),
+        ];
+        for (target, method) in methods {
+            let profile = compile_for(source, target);
+            assert_eq!(
+                remappings(&profile)[0]["binds"],
+                json!([{"type": "type_unicode", "value": "→", "method": method}])
+            );
+            assert_eq!(
+                remappings(&profile)[2]["binds"],
+                json!([{"type": "type_unicode", "value": "é", "method": method}])
+            );
+        }
+    }
+
+    #[test]
+    fn test_when_app() {
+        let source = r#"profile "Test"
+config {
+}
+layer "base" {
+    when app(~"Fire(fox)?") {
+        a = b
+    }
+    when app(glob("Code*")) {
+        a = c
+    }
+    when app("Terminal") {
+        a = d
+    }
+    e = f
+}
+"#;
+        let profile = compile(source);
+        let apps: Vec<_> = remappings(&profile).iter().map(|r| &r["app"]).collect();
+        assert_eq!(
+            apps,
+            [
+                &json!({"type": "regex", "pattern": "Fire(fox)?"}),
+                &json!({"type": "regex", "pattern": "Fire(fox)?"}),
+                &json!({"type": "glob", "pattern": "Code*"}),
+                &json!({"type": "glob", "pattern": "Code*"}),
+                &json!({"type": "exact", "value": "Terminal"}),
+                &json!({"type": "exact", "value": "Terminal"}),
+                &Value::Null,
+                &Value::Null,
+            ]
+        );
+        assert_eq!(
+            remappings(&profile)[2]["binds"],
+            json!([{"type": "press_key", "value": "C"}])
+        );
+
+        let overlap = source.replace("    e = f", "    a = f");
+        assert_eq!(compile_error(&overlap), "Conflicting statments");
+    }
+
+    #[test]
+    fn test_app_unfocused_and_window_title() {
+        let source = r#"profile "Test"
+config {
+}
+layer "base" {
+    app_unfocused("Firefox") = layer("base")
+    window_title(~"- Mozilla Firefox$") = layer("base")
+    window_title("Inbox") = layer("base")
+}
+"#;
+        assert_eq!(
+            compile(source)["layers"][0]["remappings"],
+            json!([
+                {
+                    "trigger": {"type": "app_unfocused", "app_name": "Firefox"},
+                    "binds": [{"type": "switch_layer", "value": 0}],
+                },
+                {
+                    "trigger": {
+                        "type": "window_title",
+                        "title": {"type": "regex", "pattern": "- Mozilla Firefox$"},
+                    },
+                    "binds": [{"type": "switch_layer", "value": 0}],
+                },
+                {
+                    "trigger": {
+                        "type": "window_title",
+                        "title": {"type": "exact", "value": "Inbox"},
+                    },
+                    "binds": [{"type": "switch_layer", "value": 0}],
+                },
+            ])
+        );
+
+        let twice = source.replace("\"Inbox\"", "~\"- Mozilla Firefox$\"");
+        assert_eq!(compile_error(&twice), "Conflicting statments");
+    }
+
+    #[test]
+    fn test_layer_config() {
+        let source = r#"profile "Test"
+config {
+    tap_timeout = 200
+}
+layer "base" {
+    tap(a) = b
+}
+layer "games" {
+    config {
+        tap_timeout = 120
+    }
+    tap(a) = b
+}
+"#;
+        let profile = compile(source);
+        let timeout =
+            |layer: usize| profile["layers"][layer]["remappings"][0]["triggers"][1].clone();
+        assert_eq!(timeout(0), json!({"type": "maximum_wait", "duration": 200}));
+        assert_eq!(timeout(1), json!({"type": "maximum_wait", "duration": 120}));
+
+        let parse_error = |source: &str| {
+            let mut ts = TokenStream::new(Lexer::new("test", source));
+            ast::Profile::parse(&mut ts).unwrap_err().to_string()
+        };
+        let twice = source.replace(
+            "        tap_timeout = 120\n    }",
+            "        tap_timeout = 120\n    }\n    config {\n    }",
+        );
+        assert_eq!(
+            parse_error(&twice),
+            "A layer can only have one config block"
+        );
+    }
+
+    #[test]
+    fn test_open_app_quotes_the_name() {
+        let profile = compile_for(
+            r#"profile "Test"
+config {
+}
+layer "base" {
+    a = open_app("Bob's \"App\"")
+}
+"#,
+            TargetOs::Macos,
+        );
+        assert_eq!(
+            remappings(&profile)[0]["binds"][0]["script"],
+            r#"open -a 'Bob'\''s "App"'"#
+        );
+        let profile = compile(
+            r#"profile "Test"
+config {
+}
+layer "base" {
+    a = open_app("Missing Clickr App")
+}
+"#,
+        );
+        assert_eq!(
+            remappings(&profile)[0]["binds"][0],
+            json!({
+                "type": "run_script",
+                "interpreter": "sh",
+                "script": "gtk-launch 'Missing Clickr App'",
+            })
+        );
+    }
 }
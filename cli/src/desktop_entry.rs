@@ -0,0 +1,321 @@
+//! Lookup of freedesktop.org desktop entries, used to launch apps on Linux
+use std::{
+    collections::HashSet,
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+/// An application found in one of the `applications` directories of the XDG data dirs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DesktopEntry {
+    /// The desktop file id, the path below `applications` without `.desktop` and with `-`
+    /// instead of `/`
+    pub id: String,
+    pub name: String,
+    /// The `Exec` value with the escapes of string values already replaced
+    pub exec: String,
+    /// Entries with `NoDisplay=true` are left out of menus, so they are only found by their id
+    pub no_display: bool,
+}
+
+impl DesktopEntry {
+    /// Parses the `[Desktop Entry]` group of a desktop file. Returns `None` for entries that are
+    /// hidden or cannot be launched.
+    pub fn parse(id: &str, contents: &str) -> Option<Self> {
+        let mut in_main_group = false;
+        let mut name = None;
+        let mut exec = None;
+        let mut no_display = false;
+
+        for line in contents.lines().map(str::trim) {
+            if line.starts_with('[') {
+                in_main_group = line == "[Desktop Entry]";
+                continue;
+            }
+            if !in_main_group || line.starts_with('#') {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            match (key.trim(), value.trim()) {
+                ("Name", value) => name = Some(unescape(value)),
+                ("Exec", value) => exec = Some(unescape(value)),
+                ("Type", value) if value != "Application" => return None,
+                ("Hidden", "true") => return None,
+                ("NoDisplay", value) => no_display = value == "true",
+                _ => (),
+            }
+        }
+
+        Some(Self {
+            id: id.to_string(),
+            name: name?,
+            exec: exec?,
+            no_display,
+        })
+    }
+
+    /// Splits the `Exec` value into the program and its arguments. Arguments are separated by
+    /// spaces and can be quoted with `"`, where `\` escapes the next character. The field codes
+    /// for files and urls are removed and `%c` is replaced by the name.
+    pub fn arguments(&self) -> Vec<String> {
+        let mut arguments = Vec::new();
+        let mut chars = self.exec.chars().peekable();
+        loop {
+            while chars.next_if_eq(&' ').is_some() {}
+            if chars.peek().is_none() {
+                return arguments;
+            }
+
+            let mut argument = String::new();
+            // An argument made only of field codes that expand to nothing is dropped
+            let mut is_empty = true;
+            while let Some(c) = chars.next_if(|c| *c != ' ') {
+                match c {
+                    '"' => {
+                        is_empty = false;
+                        while let Some(c) = chars.next() {
+                            match c {
+                                '"' => break,
+                                '\\' => argument.extend(chars.next()),
+                                c => argument.push(c),
+                            }
+                        }
+                    }
+                    '%' => match chars.next() {
+                        Some('%') => {
+                            is_empty = false;
+                            argument.push('%');
+                        }
+                        Some('c') => {
+                            is_empty = false;
+                            argument.push_str(&self.name);
+                        }
+                        // %f %F %u %U %i %k and the deprecated codes expand to nothing without
+                        // arguments
+                        _ => (),
+                    },
+                    c => {
+                        is_empty = false;
+                        argument.push(c);
+                    }
+                }
+            }
+            if !is_empty {
+                arguments.push(argument);
+            }
+        }
+    }
+
+    /// The `Exec` command line as a shell command, every argument is quoted for `sh`
+    pub fn launch_command(&self) -> String {
+        self.arguments()
+            .iter()
+            .map(|argument| shell_quote(argument))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Returns true if this entry is the app with the given name or desktop file id
+    fn matches(&self, app_name: &str) -> bool {
+        let app_name = app_name.trim_end_matches(".desktop");
+        self.id.eq_ignore_ascii_case(app_name)
+            || (!self.no_display && self.name.eq_ignore_ascii_case(app_name))
+    }
+}
+
+/// Replaces the `\s`, `\n`, `\t`, `\r` and `\\` escapes of a string value
+fn unescape(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('s') => result.push(' '),
+            Some('n') => result.push('\n'),
+            Some('t') => result.push('\t'),
+            Some('r') => result.push('\r'),
+            Some('\\') => result.push('\\'),
+            Some(other) => result.extend(['\\', other]),
+            None => result.push('\\'),
+        }
+    }
+    result
+}
+
+/// Quotes the argument for `sh` unless it only has characters without a special meaning
+pub fn shell_quote(argument: &str) -> String {
+    let is_plain = |c: char| c.is_ascii_alphanumeric() || "%+,-./:=@_".contains(c);
+    if !argument.is_empty() && argument.chars().all(is_plain) {
+        argument.to_string()
+    } else {
+        format!("'{}'", argument.replace('\'', r"'\''"))
+    }
+}
+
+/// The `applications` directories in order of precedence, as given by the XDG base directory
+/// specification
+pub fn application_dirs() -> Vec<PathBuf> {
+    let data_home = env::var_os("XDG_DATA_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")));
+    let data_dirs = env::var("XDG_DATA_DIRS")
+        .ok()
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".to_string());
+
+    data_home
+        .into_iter()
+        .chain(data_dirs.split(':').map(PathBuf::from))
+        .map(|dir| dir.join("applications"))
+        .collect()
+}
+
+/// The desktop files below the directory paired with their desktop file id, the id of
+/// `kde/foo.desktop` is `kde-foo`
+fn desktop_files(dir: &Path, id_prefix: &str) -> Vec<(String, PathBuf)> {
+    let Ok(files) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut result = Vec::new();
+    for path in files.filter_map(Result::ok).map(|file| file.path()) {
+        let Some(file_name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        if path.is_dir() {
+            result.extend(desktop_files(&path, &format!("{id_prefix}{file_name}-")));
+        } else if let Some(id) = file_name.strip_suffix(".desktop") {
+            result.push((format!("{id_prefix}{id}"), path));
+        }
+    }
+    result
+}
+
+/// Finds the desktop entry for an app by its display name or desktop file id
+pub fn find(app_name: &str) -> Option<DesktopEntry> {
+    find_in(&application_dirs(), app_name)
+}
+
+/// Finds the desktop entry in the given `applications` directories. The first file with an id
+/// wins, even if it is hidden, so an entry can be removed by hiding it in an earlier directory.
+fn find_in(dirs: &[PathBuf], app_name: &str) -> Option<DesktopEntry> {
+    let mut seen_ids = HashSet::new();
+    dirs.iter()
+        .flat_map(|dir| desktop_files(dir, ""))
+        .filter(|(id, _)| seen_ids.insert(id.clone()))
+        .filter_map(|(id, path)| DesktopEntry::parse(&id, &fs::read_to_string(&path).ok()?))
+        .find(|entry| entry.matches(app_name))
+}
+
+#[cfg(test)]
+mod desktop_entry_tests {
+    use super::*;
+
+    const FIREFOX: &str = "\
+[Desktop Entry]
+Version=1.0
+Name=Firefox Web Browser
+Exec=firefox %u
+Type=Application
+
+[Desktop Action new-window]
+Name=Open a New Window
+Exec=firefox --new-window %u
+";
+
+    #[test]
+    fn test_parse() {
+        let entry = DesktopEntry::parse("firefox", FIREFOX).unwrap();
+        assert_eq!(entry.name, "Firefox Web Browser");
+        assert_eq!(entry.exec, "firefox %u");
+    }
+
+    #[test]
+    fn test_parse_hidden() {
+        let hidden = FIREFOX.replace("Version=1.0", "Hidden=true");
+        assert_eq!(DesktopEntry::parse("firefox", &hidden), None);
+    }
+
+    #[test]
+    fn test_parse_no_display() {
+        let no_display = FIREFOX.replace("Version=1.0", "NoDisplay=true");
+        let entry = DesktopEntry::parse("firefox", &no_display).unwrap();
+        assert!(entry.no_display);
+        assert!(entry.matches("firefox"));
+        assert!(!entry.matches("Firefox Web Browser"));
+    }
+
+    #[test]
+    fn test_arguments() {
+        let mut entry = DesktopEntry::parse("firefox", FIREFOX).unwrap();
+        assert_eq!(entry.arguments(), ["firefox"]);
+
+        entry.exec = r#""/opt/my app/run" --name "say \"hi\"" "" %U"#.to_string();
+        assert_eq!(
+            entry.arguments(),
+            ["/opt/my app/run", "--name", r#"say "hi""#, ""]
+        );
+    }
+
+    #[test]
+    fn test_launch_command() {
+        let mut entry = DesktopEntry::parse("firefox", FIREFOX).unwrap();
+        assert_eq!(entry.launch_command(), "firefox");
+
+        entry.exec = "env A=100%% app %F --title %c".to_string();
+        assert_eq!(
+            entry.launch_command(),
+            "env A=100% app --title 'Firefox Web Browser'"
+        );
+
+        entry.name = "Bob's $HOME".to_string();
+        entry.exec = r#""/opt/my app/run" --title=%c"#.to_string();
+        assert_eq!(
+            entry.launch_command(),
+            r#"'/opt/my app/run' '--title=Bob'\''s $HOME'"#
+        );
+    }
+
+    #[test]
+    fn test_matches() {
+        let entry = DesktopEntry::parse("firefox", FIREFOX).unwrap();
+        assert!(entry.matches("firefox"));
+        assert!(entry.matches("firefox.desktop"));
+        assert!(entry.matches("Firefox Web Browser"));
+        assert!(!entry.matches("Firefox Developer Edition"));
+    }
+
+    #[test]
+    fn test_find() {
+        let root = env::temp_dir().join(format!("clickr-desktop-entries-{}", std::process::id()));
+        let (user, system) = (root.join("user"), root.join("system"));
+        fs::create_dir_all(user.join("kde")).unwrap();
+        fs::create_dir_all(&system).unwrap();
+        fs::write(system.join("firefox.desktop"), FIREFOX).unwrap();
+        fs::write(
+            user.join("kde/konsole.desktop"),
+            FIREFOX.replace("Firefox Web Browser", "Konsole"),
+        )
+        .unwrap();
+        let dirs = [user.clone(), system];
+
+        assert_eq!(find_in(&dirs, "Firefox Web Browser").unwrap().id, "firefox");
+        assert_eq!(find_in(&dirs, "Konsole").unwrap().id, "kde-konsole");
+        assert_eq!(find_in(&dirs, "kde-konsole").unwrap().name, "Konsole");
+
+        // Hiding the entry in an earlier directory removes it
+        fs::write(
+            user.join("firefox.desktop"),
+            "[Desktop Entry]\nHidden=true\n",
+        )
+        .unwrap();
+        assert_eq!(find_in(&dirs, "firefox"), None);
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -12,28 +12,61 @@ use std::{fmt::Formatter, sync::LazyLock};
 static IDENT_REGEX: LazyLock<Regex> =
     LazyLock::new(|| Regex::new("^[a-zA-Z][a-zA-Z0-9_]*").expect("regex invalid"));
 
+static PARAM_REF_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\$[a-zA-Z][a-zA-Z0-9_]*").expect("regex invalid"));
+
+/// A backslash escapes the next character, unless that leaves the string without a closing quote
+/// on the line. Then it is kept as written, like in `"C:\Users\"`.
 static STRING_REGEX: LazyLock<Regex> =
-    LazyLock::new(|| Regex::new(r#"^"[^"]*""#).expect("regex invalid"));
+    LazyLock::new(|| Regex::new(r#"^"(?:[^"\\\r\n]|\\[^\r\n]|\\)*""#).expect("regex invalid"));
+
+static CHAR_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^'(?:\\['\\]|[^'\\\n])'").expect("regex invalid"));
+
+static MULTILINE_STRING_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"^"""(?s:.*?)""""#).expect("regex invalid"));
 
 static INT_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new("^[0-9]+").expect("regex invalid"));
 
+static DURATION_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[0-9]+(ms|s)\b").expect("regex invalid"));
+
 fn bytes_to_keyword(bytes: &[u8]) -> Option<TokenType> {
     match bytes {
         b"profile" => Some(TokenType::Profile),
         b"config" => Some(TokenType::Config),
+        b"metadata" => Some(TokenType::Metadata),
+        b"param" => Some(TokenType::Param),
+        b"toggle" => Some(TokenType::Toggle),
         b"layer" => Some(TokenType::Layer),
         b"tap" => Some(TokenType::Tap),
         b"hold" => Some(TokenType::Hold),
         b"chord" => Some(TokenType::Chord),
         b"sequence" => Some(TokenType::Sequence),
         b"combo" => Some(TokenType::Combo),
+        b"swap" => Some(TokenType::Swap),
+        b"grid" => Some(TokenType::Grid),
         b"run" => Some(TokenType::Run),
         b"open_app" => Some(TokenType::OpenApp),
         b"app_focused" => Some(TokenType::AppFocused),
+        b"app_unfocused" => Some(TokenType::AppUnfocused),
+        b"window_title" => Some(TokenType::WindowTitle),
+        b"when" => Some(TokenType::When),
+        b"if" => Some(TokenType::If),
+        b"held" => Some(TokenType::Held),
+        b"on" => Some(TokenType::On),
+        b"app" => Some(TokenType::App),
+        b"caps_word" => Some(TokenType::CapsWord),
+        b"set" => Some(TokenType::Set),
+        b"flip" => Some(TokenType::Flip),
+        b"mouse_move" => Some(TokenType::MouseMove),
+        b"unicode" | b"char" => Some(TokenType::Unicode),
+        b"notify" => Some(TokenType::Notify),
         b"none" => Some(TokenType::NoneKw),
         b"capture" => Some(TokenType::Capture),
         b"release" => Some(TokenType::Release),
         b"wait" => Some(TokenType::Wait),
+        b"within" => Some(TokenType::Within),
         b"true" => Some(TokenType::True),
         b"false" => Some(TokenType::False),
         _ => None,
@@ -59,6 +92,7 @@ impl<'a> Lexer<'a> {
     fn create_token(&mut self, kind: TokenType, len: usize) -> Token<'a> {
         let token = Token {
             start: self.cur,
+            len,
             bytes: str::from_utf8(&self.bytes[self.cur..(self.cur + len)])
                 .expect("lexer bytes must come from a string"),
             kind,
@@ -85,6 +119,7 @@ impl<'a> Iterator for Lexer<'a> {
                         self.cur += 1;
                         Some(Token {
                             start: self.cur - 1,
+                            len: 0,
                             bytes: "",
                             kind: TokenType::Eof,
                         })
@@ -108,6 +143,9 @@ impl<'a> Iterator for Lexer<'a> {
                 }
 
                 Some(b'\n') => return Some(self.create_token(TokenType::Newline, 1)),
+                Some(b'\r') if self.bytes.get(self.cur + 1) == Some(&b'\n') => {
+                    return Some(self.create_token(TokenType::Newline, 2));
+                }
 
                 Some(b'{') => return Some(self.create_token(TokenType::LCurly, 1)),
                 Some(b'}') => return Some(self.create_token(TokenType::RCurly, 1)),
@@ -119,12 +157,46 @@ impl<'a> Iterator for Lexer<'a> {
                 Some(b'=') => return Some(self.create_token(TokenType::Equals, 1)),
                 Some(b'^') => return Some(self.create_token(TokenType::Caret, 1)),
                 Some(b'_') => return Some(self.create_token(TokenType::Underscore, 1)),
+                Some(b'-') => return Some(self.create_token(TokenType::Minus, 1)),
+                Some(b'~') => return Some(self.create_token(TokenType::Tilde, 1)),
+                Some(b'*') => return Some(self.create_token(TokenType::Star, 1)),
+                Some(b'<') if self.bytes[self.cur..].starts_with(b"<->") => {
+                    return Some(self.create_token(TokenType::SwapArrow, 3));
+                }
+                Some(b':') => return Some(self.create_token(TokenType::Colon, 1)),
+                Some(b'!') => return Some(self.create_token(TokenType::Bang, 1)),
+                Some(b'&') if self.bytes[self.cur..].starts_with(b"&&") => {
+                    return Some(self.create_token(TokenType::AndAnd, 2));
+                }
 
                 Some(b'0'..=b'9') => {
+                    if let Some(duration_lit) = DURATION_REGEX.find(&self.bytes[self.cur..]) {
+                        return Some(self.create_token(TokenType::DurationLit, duration_lit.end()));
+                    }
                     let int_lit = INT_REGEX.find(&self.bytes[self.cur..]).unwrap();
                     return Some(self.create_token(TokenType::IntLit, int_lit.end()));
                 }
 
+                Some(b'"') if self.bytes[self.cur..].starts_with(b"\"\"\"") => {
+                    match MULTILINE_STRING_REGEX.find(&self.bytes[self.cur..]) {
+                        Some(str_lit) => {
+                            return Some(self.create_token(TokenType::StringLit, str_lit.end()))
+                        }
+                        None => exit_with_error(
+                            miette!(
+                                severity = Severity::Error,
+                                labels =
+                                    vec![LabeledSpan::at_offset(self.cur, "Unterminated string")],
+                                "Multi-line string literal must end with \"\"\""
+                            )
+                            .with_source_code(NamedSource::new(
+                                self.source_name,
+                                self.bytes.to_vec(),
+                            )),
+                        ),
+                    }
+                }
+
                 Some(b'"') => match STRING_REGEX.find(&self.bytes[self.cur..]) {
                     Some(str_lit) => {
                         return Some(self.create_token(TokenType::StringLit, str_lit.end()))
@@ -133,12 +205,34 @@ impl<'a> Iterator for Lexer<'a> {
                         miette!(
                             severity = Severity::Error,
                             labels = vec![LabeledSpan::at_offset(self.cur, "Unterminated string")],
+                            help = "Use \"\"\" to start a string that spans multiple lines",
                             "String literal must end on the same line"
                         )
                         .with_source_code(NamedSource::new(self.source_name, self.bytes.to_vec())),
                     ),
                 },
 
+                Some(b'$')
+                    if let Some(param_ref) = PARAM_REF_REGEX.find(&self.bytes[self.cur..]) =>
+                {
+                    return Some(self.create_token(TokenType::ParamRef, param_ref.end()));
+                }
+
+                Some(b'\'') => match CHAR_REGEX.find(&self.bytes[self.cur..]) {
+                    Some(char_lit) => {
+                        return Some(self.create_token(TokenType::CharLit, char_lit.end()))
+                    }
+                    None => exit_with_error(
+                        miette!(
+                            severity = Severity::Error,
+                            labels = vec![LabeledSpan::at_offset(self.cur, "Invalid character")],
+                            help = "Character keys hold a single character, like 'q' or '\\''",
+                            "Invalid character key"
+                        )
+                        .with_source_code(NamedSource::new(self.source_name, self.bytes.to_vec())),
+                    ),
+                },
+
                 Some(b'a'..=b'z') | Some(b'A'..=b'Z') => {
                     let var = IDENT_REGEX
                         .find(&self.bytes[self.cur..])
@@ -173,6 +267,8 @@ impl<'a> Iterator for Lexer<'a> {
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Token<'a> {
     start: usize,
+    // Only differs from the length of `bytes` for tokens moved with `at`
+    len: usize,
     bytes: &'a str,
     kind: TokenType,
 }
@@ -183,7 +279,17 @@ impl Token<'_> {
     }
 
     pub fn loc(&self) -> Span {
-        Span::new(self.start, self.bytes.len())
+        Span::new(self.start, self.len)
+    }
+
+    /// The same token placed at `loc`, the value of a parameter takes the place of the `$name`
+    /// reference it was substituted for
+    pub fn at(self, loc: Span) -> Self {
+        Self {
+            start: loc.start(),
+            len: loc.len(),
+            ..self
+        }
     }
 
     pub fn start(&self) -> usize {
@@ -193,30 +299,101 @@ impl Token<'_> {
     pub fn bytes(&self) -> &str {
         self.bytes
     }
+
+    /// Returns the text of a string literal token with its quotes removed and the escapes `\"`,
+    /// `\\`, `\n` and `\t` replaced, other backslashes are kept as written. Multi-line strings are
+    /// raw, only a line break right after the opening quotes is dropped.
+    pub fn string_value(&self) -> String {
+        assert_eq!(self.kind, TokenType::StringLit);
+
+        if let Some(raw) = self
+            .bytes
+            .strip_prefix("\"\"\"")
+            .and_then(|s| s.strip_suffix("\"\"\""))
+        {
+            let raw = raw
+                .strip_prefix("\r\n")
+                .or_else(|| raw.strip_prefix('\n'))
+                .unwrap_or(raw);
+            return raw.to_string();
+        }
+
+        let inner = &self.bytes[1..self.bytes.len() - 1];
+        let mut value = String::with_capacity(inner.len());
+        let mut chars = inner.chars().peekable();
+        while let Some(c) = chars.next() {
+            match (c, chars.peek()) {
+                ('\\', Some('"')) => value.push('"'),
+                ('\\', Some('\\')) => value.push('\\'),
+                ('\\', Some('n')) => value.push('\n'),
+                ('\\', Some('t')) => value.push('\t'),
+                (c, _) => {
+                    value.push(c);
+                    continue;
+                }
+            }
+            chars.next();
+        }
+        value
+    }
+
+    pub fn char_value(&self) -> char {
+        assert_eq!(self.kind, TokenType::CharLit);
+
+        let inner = &self.bytes[1..self.bytes.len() - 1];
+        let mut chars = inner.chars();
+        match chars.next() {
+            Some('\\') => chars.next(),
+            c => c,
+        }
+        .expect("lexer only creates character literals with a character")
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TokenType {
     Profile,
     Config,
+    Metadata,
+    Param,
+    Toggle,
     Layer,
     Tap,
     Hold,
     Chord,
     Sequence,
     Combo,
+    Swap,
+    Grid,
     Run,
     OpenApp,
     AppFocused,
+    AppUnfocused,
+    WindowTitle,
+    When,
+    If,
+    Held,
+    On,
+    App,
+    CapsWord,
+    Set,
+    Flip,
+    MouseMove,
+    Unicode,
+    Notify,
     NoneKw,
     Capture,
     Release,
     Wait,
+    Within,
     True,
     False,
     Ident,
     IntLit,
+    DurationLit,
     StringLit,
+    CharLit,
+    ParamRef,
     Equals,
     Comma,
     LCurly,
@@ -229,6 +406,12 @@ pub enum TokenType {
     Newline,
     Caret,
     Underscore,
+    Minus,
+    Tilde,
+    Star,
+    SwapArrow,
+    Bang,
+    AndAnd,
     Eof,
 }
 
@@ -237,3 +420,84 @@ impl Display for TokenType {
         write!(f, "{:?}", self)
     }
 }
+
+#[cfg(test)]
+mod lex_tests {
+    use super::*;
+
+    fn lex_string(source: &str) -> String {
+        let token = Lexer::new("test", source).next().unwrap();
+        assert_eq!(token.kind(), TokenType::StringLit);
+        assert_eq!(token.bytes(), source);
+        token.string_value()
+    }
+
+    #[test]
+    fn test_string() {
+        assert_eq!(lex_string(r#""hello""#), "hello");
+        assert_eq!(lex_string(r#""""#), "");
+    }
+
+    #[test]
+    fn test_string_escapes() {
+        assert_eq!(lex_string(r#""say \"hi\"""#), r#"say "hi""#);
+        assert_eq!(lex_string(r#""a\\b""#), r"a\b");
+        assert_eq!(lex_string(r#""a\nb\tc""#), "a\nb\tc");
+    }
+
+    #[test]
+    fn test_unknown_escapes_are_kept() {
+        assert_eq!(lex_string(r#""\d+\.txt""#), r"\d+\.txt");
+        assert_eq!(lex_string(r#""C:\Users\""#), r"C:\Users\");
+        assert_eq!(lex_string(r#""\""#), r"\");
+
+        let kinds =
+            |source: &str| -> Vec<_> { Lexer::new("test", source).map(|t| t.kind()).collect() };
+        assert_eq!(
+            kinds(r#""\" = x"#),
+            [
+                TokenType::StringLit,
+                TokenType::Equals,
+                TokenType::Ident,
+                TokenType::Eof
+            ]
+        );
+        // Another quote on the line closes the string instead
+        assert_eq!(lex_string(r#""\" = ""#), r#"" = "#);
+        assert_eq!(
+            kinds(r#""\\" = "|""#),
+            [
+                TokenType::StringLit,
+                TokenType::Equals,
+                TokenType::StringLit,
+                TokenType::Eof
+            ]
+        );
+    }
+
+    #[test]
+    fn test_crlf() {
+        let tokens: Vec<_> = Lexer::new("test", "a = \"\"\"\r\nline\r\n\"\"\"\r\nb\r\n").collect();
+        let kinds: Vec<_> = tokens.iter().map(|t| t.kind()).collect();
+        assert_eq!(
+            kinds,
+            [
+                TokenType::Ident,
+                TokenType::Equals,
+                TokenType::StringLit,
+                TokenType::Newline,
+                TokenType::Ident,
+                TokenType::Newline,
+                TokenType::Eof
+            ]
+        );
+        assert_eq!(tokens[2].string_value(), "line\r\n");
+    }
+
+    #[test]
+    fn test_multiline_string() {
+        let source = "\"\"\"\necho \"one\"\necho \\n\n\"\"\"";
+        assert_eq!(lex_string(source), "echo \"one\"\necho \\n\n");
+        assert_eq!(lex_string("\"\"\"\r\nline\r\n\"\"\""), "line\r\n");
+    }
+}
@@ -7,14 +7,19 @@ pub mod ast;
 pub mod check;
 pub mod cli;
 pub mod compiled;
+pub mod desktop_entry;
 pub mod ipc;
 pub mod lex;
 pub mod parse;
+pub mod target;
 pub mod utils;
 
+use crate::ast::param::resolve_params;
+pub use crate::ast::param::ParamOverrides;
 use crate::ipc::{send_pause, send_profile, send_resume};
 use crate::lex::Lexer;
 use crate::parse::{Parse, TokenStream};
+use crate::target::TargetOs;
 
 #[derive(Debug, Error)]
 pub enum ClientError {
@@ -29,21 +34,66 @@ pub enum ClientError {
 
     #[error("Daemon communication error: {0}")]
     Ipc(#[from] ipc::IpcError),
+
+    #[error("Failed to serialize compiled profile: {0}")]
+    Json(#[from] serde_json::Error),
 }
 
-fn parse_profile(profile_path: &str) -> Result<(ast::Profile, Arc<String>), ClientError> {
+fn parse_profile(
+    profile_path: &str,
+    params: &ParamOverrides,
+) -> Result<(ast::Profile, Arc<String>), ClientError> {
     let contents = Arc::new(fs::read_to_string(profile_path).map_err(ClientError::Io)?);
 
     let lexer = Lexer::new(profile_path, &contents);
-    let mut ts = TokenStream::new(lexer);
-
-    let profile = ast::Profile::parse(&mut ts).map_err(|err| {
-        ClientError::Parse(err.with_source_code(NamedSource::new(profile_path, contents.clone())))
-    })?;
+    let profile = resolve_params(lexer, params)
+        .and_then(|tokens| ast::Profile::parse(&mut TokenStream::with_tokens(lexer, tokens)))
+        .map_err(|err| {
+            ClientError::Parse(
+                err.with_source_code(NamedSource::new(profile_path, contents.clone())),
+            )
+        })?;
 
     Ok((profile, contents))
 }
 
+/// A profile that was parsed and checked, and compiled if checking found no errors
+struct Build {
+    compiled: Option<compiled::Profile>,
+    reports: Vec<Report>,
+    contents: Arc<String>,
+}
+
+/// Parses, checks and compiles the profile. Every platform is checked, so mistakes in `on`
+/// blocks for other platforms are found too, but only the statements for `target` are compiled.
+fn build_profile(
+    profile_path: &str,
+    target: TargetOs,
+    params: ParamOverrides,
+) -> Result<Build, ClientError> {
+    let (profile, contents) = parse_profile(profile_path, &params)?;
+
+    let mut reports = profile.check(target);
+    let has_errors = reports
+        .iter()
+        .any(|r| r.severity() == Some(Severity::Error));
+    let compiled = if has_errors {
+        None
+    } else {
+        profile
+            .for_target(target)
+            .compile(target, &contents, &params)
+            .map_err(|err| reports.push(err))
+            .ok()
+    };
+
+    Ok(Build {
+        compiled,
+        reports,
+        contents,
+    })
+}
+
 fn print_reports(reports: Vec<Report>, filename: &str, contents: Arc<String>) -> (usize, usize) {
     let mut errors = 0;
     let mut warnings = 0;
@@ -51,7 +101,8 @@ fn print_reports(reports: Vec<Report>, filename: &str, contents: Arc<String>) ->
     for report in reports {
         match report.severity() {
             Some(Severity::Error) | None => errors += 1, // Treat no severity as error
-            _ => warnings += 1,
+            Some(Severity::Warning) => warnings += 1,
+            Some(Severity::Advice) => {}
         }
         eprintln!(
             "{:?}",
@@ -61,23 +112,20 @@ fn print_reports(reports: Vec<Report>, filename: &str, contents: Arc<String>) ->
     (errors, warnings)
 }
 
-pub fn check_profile(profile_path: &str) -> Result<(), ClientError> {
-    let (profile, contents) = parse_profile(profile_path)?;
-
-    let mut reports = profile.check();
-    let initial_error_count = reports
-        .iter()
-        .filter(|r| r.severity() == Some(Severity::Error))
-        .count();
-    if initial_error_count == 0 {
-        reports.extend(profile.compile().err())
-    };
-
-    if reports.is_empty() {
+pub fn check_profile(
+    profile_path: &str,
+    target: TargetOs,
+    params: ParamOverrides,
+) -> Result<(), ClientError> {
+    let Build {
+        reports, contents, ..
+    } = build_profile(profile_path, target, params)?;
+
+    let (errors, warnings) = print_reports(reports, profile_path, contents);
+    if errors == 0 && warnings == 0 {
         println!("Check passed. Profile is valid.");
         Ok(())
     } else {
-        let (errors, warnings) = print_reports(reports, profile_path, contents);
         println!(
             "Check finished: {} error(s), {} warning(s)",
             errors, warnings
@@ -86,15 +134,18 @@ pub fn check_profile(profile_path: &str) -> Result<(), ClientError> {
     }
 }
 
-pub fn load_profile(profile_path: &str) -> Result<(), ClientError> {
-    let (profile, contents) = parse_profile(profile_path)?;
-    let reports = profile.check();
-    let initial_error_count = reports
-        .iter()
-        .filter(|r| r.severity() == Some(Severity::Error))
-        .count();
-
-    if initial_error_count > 0 {
+pub fn load_profile(
+    profile_path: &str,
+    target: TargetOs,
+    params: ParamOverrides,
+) -> Result<(), ClientError> {
+    let Build {
+        compiled,
+        reports,
+        contents,
+    } = build_profile(profile_path, target, params)?;
+
+    let Some(compiled) = compiled else {
         let (errors, warnings) = print_reports(reports, profile_path, contents);
         println!(
             "Check finished: {} error(s), {} warning(s)",
@@ -102,21 +153,52 @@ pub fn load_profile(profile_path: &str) -> Result<(), ClientError> {
         );
         eprintln!("Profile is invalid. Aborting load.");
         return Err(ClientError::Validation);
-    }
+    };
 
-    match profile.compile() {
-        Ok(compiled) => {
-            println!("Profile is valid and compiled. Sending to daemon...");
-            send_profile(&compiled)?;
-            println!("Successfully loaded profile.");
-            Ok(())
-        }
-        Err(compile_err) => {
-            print_reports(vec![compile_err], profile_path, contents);
-            eprintln!("Profile failed compilation check. Aborting load.");
-            Err(ClientError::Validation)
-        }
+    println!("Profile is valid and compiled. Sending to daemon...");
+    send_profile(&compiled)?;
+    println!(
+        "Successfully loaded profile {}{} ({}).",
+        compiled.profile_name,
+        compiled
+            .metadata
+            .version
+            .as_ref()
+            .map(|v| format!(" {v}"))
+            .unwrap_or_default(),
+        compiled.metadata.source_hash
+    );
+    Ok(())
+}
+
+/// Checks and compiles the profile, writing the compiled profile to `output` or stdout
+pub fn compile_profile(
+    profile_path: &str,
+    target: TargetOs,
+    params: ParamOverrides,
+    output: Option<&str>,
+) -> Result<(), ClientError> {
+    let Build {
+        compiled,
+        reports,
+        contents,
+    } = build_profile(profile_path, target, params)?;
+
+    let Some(compiled) = compiled else {
+        let (errors, warnings) = print_reports(reports, profile_path, contents);
+        eprintln!(
+            "Check finished: {} error(s), {} warning(s)",
+            errors, warnings
+        );
+        return Err(ClientError::Validation);
+    };
+
+    let json = serde_json::to_string_pretty(&compiled)?;
+    match output {
+        Some(output_path) => fs::write(output_path, json + "\n")?,
+        None => println!("{}", json),
     }
+    Ok(())
 }
 
 /// Requests the key binder to pauses remappings
@@ -4,7 +4,9 @@ use std::process::exit;
 use clickr_cli::{
     check_profile,
     cli::{ClickrArgs, ClickrSubcommand},
-    get_status, load_profile, pause_keybinder, resume_keybinder, ClientError,
+    compile_profile, get_status, load_profile, pause_keybinder, resume_keybinder,
+    target::TargetOs,
+    ClientError,
 };
 
 fn main() {
@@ -12,22 +14,56 @@ fn main() {
 
     // Run the appropriate command and store the result
     let result = match args.mode {
-        ClickrSubcommand::Load { profile } => {
+        ClickrSubcommand::Load {
+            profile,
+            target,
+            set,
+        } => {
             if let Some(profile_file) = profile {
-                load_profile(&profile_file)
+                load_profile(
+                    &profile_file,
+                    target.unwrap_or_else(TargetOs::host),
+                    set.into_iter().collect(),
+                )
             } else {
                 eprintln!("Error: No profile file provided for 'load'");
                 exit(2);
             }
         }
-        ClickrSubcommand::Check { profile } => {
+        ClickrSubcommand::Check {
+            profile,
+            target,
+            set,
+        } => {
             if let Some(profile_file) = profile {
-                check_profile(&profile_file)
+                check_profile(
+                    &profile_file,
+                    target.unwrap_or_else(TargetOs::host),
+                    set.into_iter().collect(),
+                )
             } else {
                 eprintln!("Error: No profile file provided for 'check'");
                 exit(2);
             }
         }
+        ClickrSubcommand::Compile {
+            profile,
+            target,
+            set,
+            output,
+        } => {
+            if let Some(profile_file) = profile {
+                compile_profile(
+                    &profile_file,
+                    target.unwrap_or_else(TargetOs::host),
+                    set.into_iter().collect(),
+                    output.as_deref(),
+                )
+            } else {
+                eprintln!("Error: No profile file provided for 'compile'");
+                exit(2);
+            }
+        }
         ClickrSubcommand::Status {} => get_status(),
 
         ClickrSubcommand::Show { .. } => {
@@ -49,6 +85,9 @@ fn main() {
             ClientError::Ipc(ipc_err) => {
                 eprintln!("Daemon Communication Error: {}", ipc_err);
             }
+            ClientError::Json(json_err) => {
+                eprintln!("Serialization Error: {}", json_err);
+            }
         }
         exit(1);
     }
@@ -1,7 +1,12 @@
-use std::collections::VecDeque;
+use std::{
+    collections::{HashMap, VecDeque},
+    mem::discriminant,
+    time::Duration,
+    vec,
+};
 
 use crate::{
-    ast::Behavior,
+    ast::{Behavior, TriggerOption},
     lex::{Lexer, Token, TokenType},
     utils::{Span, Spanned},
 };
@@ -45,11 +50,34 @@ pub fn parse_sequence<P: Parse>(
             break;
         }
         expect_tokens(ts, [delimiter])?;
+        // Allow a trailing delimiter
+        if ts.peek_type() == Some(terminator) {
+            break;
+        }
     }
 
     Ok(items.into_boxed_slice())
 }
 
+/// Parses `{ P newline ... }` and returns a boxed slice of P
+///
+/// The opening brace may be on its own line and a block may be written on a single line.
+pub fn parse_block<P: Parse>(ts: &mut TokenStream) -> miette::Result<Box<[Spanned<P>]>> {
+    skip_newline(ts)?;
+    expect_tokens(ts, [TokenType::LCurly])?;
+    skip_newline(ts)?;
+    let items = parse_sequence_trailing(ts, TokenType::Newline, TokenType::RCurly)?;
+    expect_tokens(ts, [TokenType::RCurly])?;
+    Ok(items)
+}
+
+pub fn skip_newline(ts: &mut TokenStream) -> miette::Result<()> {
+    if next_match!(ts, TokenType::Newline) {
+        expect_tokens(ts, [TokenType::Newline])?;
+    }
+    Ok(())
+}
+
 pub fn parse_square_bracket_list<P: Parse>(
     ts: &mut TokenStream,
 ) -> miette::Result<Box<[Spanned<P>]>> {
@@ -59,26 +87,62 @@ pub fn parse_square_bracket_list<P: Parse>(
     Ok(seq)
 }
 
-pub fn parse_optional_trigger_args(
-    ts: &mut TokenStream,
-) -> miette::Result<(Option<Spanned<Behavior>>, Option<Spanned<usize>>)> {
+pub type TriggerArgs = (
+    Option<Spanned<Behavior>>,
+    Option<Spanned<usize>>,
+    Option<Spanned<Duration>>,
+);
+
+pub fn parse_optional_trigger_args(ts: &mut TokenStream) -> miette::Result<TriggerArgs> {
     let mut behavior = None;
     let mut timeout = None;
-    if !next_match!(ts, TokenType::RParen) {
+    let mut prior_idle = None;
+    // The positional arguments end at the first `name = value` option
+    if !next_match!(ts, TokenType::RParen)
+        && !next_match!(ts, TokenType::Comma, TokenType::Ident, TokenType::Equals)
+    {
         expect_tokens(ts, [TokenType::Comma])?;
         behavior = Some(Behavior::parse_spanned(ts)?);
     }
-    if !next_match!(ts, TokenType::RParen) {
+    if !next_match!(ts, TokenType::RParen)
+        && !next_match!(ts, TokenType::Comma, TokenType::Ident, TokenType::Equals)
+    {
         expect_tokens(ts, [TokenType::Comma])?;
         timeout = Some(usize::parse_spanned(ts)?);
     }
+    let mut seen = HashMap::new();
+    while !next_match!(ts, TokenType::RParen) {
+        expect_tokens(ts, [TokenType::Comma])?;
+        let option = TriggerOption::parse_spanned(ts)?;
+        if let Some(original) = seen.insert(discriminant(&option.value), option.span) {
+            return Err(miette!(
+                severity = Severity::Error,
+                labels = vec![
+                    LabeledSpan::new(
+                        Some("original option".to_string()),
+                        original.start(),
+                        original.len()
+                    ),
+                    LabeledSpan::new(
+                        Some("duplicate option".to_string()),
+                        option.span.start(),
+                        option.span.len()
+                    ),
+                ],
+                "Duplicate trigger option"
+            ));
+        }
+        match option.value {
+            TriggerOption::RequirePriorIdle(duration) => prior_idle = Some(duration),
+        }
+    }
     expect_tokens(ts, [TokenType::RParen])?;
 
-    Ok((behavior, timeout))
+    Ok((behavior, timeout, prior_idle))
 }
 
 /// Parses P delimiter ... and returns a boxed slice of P
-/// Does not consume the terminating token
+/// Does not consume the terminating token, the delimiter may be left out before it
 pub fn parse_sequence_trailing<P: Parse>(
     ts: &mut TokenStream,
     delimiter: TokenType,
@@ -93,7 +157,9 @@ pub fn parse_sequence_trailing<P: Parse>(
 
     while ts.peek_type() != Some(terminator) {
         items.push(P::parse_spanned(ts)?);
-        expect_tokens(ts, [delimiter])?;
+        if ts.peek_type() != Some(terminator) {
+            expect_tokens(ts, [delimiter])?;
+        }
     }
 
     Ok(items.into_boxed_slice())
@@ -105,7 +171,16 @@ pub struct TokenStream<'a> {
     // The front of the peeked queue contains the next token to be processed.
     peeked: VecDeque<Token<'a>>,
     lexer: Lexer<'a>,
+    // The tokens of the lexer, with parameters already substituted if they were resolved
+    tokens: vec::IntoIter<Token<'a>>,
+    // The last token returned by the iterator
     prev_token: Option<Token<'a>>,
+    // The type of the last token taken from the lexer, used to collapse repeated newlines
+    last_lexed: Option<TokenType>,
+    // A token taken from the lexer to look past a comma
+    lookahead: Option<Token<'a>>,
+    // How many parentheses and square brackets are open, newlines are ignored inside of them
+    bracket_depth: usize,
 }
 
 impl<'a> Iterator for TokenStream<'a> {
@@ -113,27 +188,66 @@ impl<'a> Iterator for TokenStream<'a> {
 
     /// Returns the next token from the peeked queue or the lexer.
     fn next(&mut self) -> Option<Self::Item> {
-        self.peeked.pop_front().or_else(|| loop {
-            let next = self.lexer.next();
-            let next_type = next.map(|t| t.kind());
-            let prev_token_type = self.prev_token.map(|t| t.kind());
-            if next_type == Some(TokenType::Newline) && prev_token_type == Some(TokenType::Newline)
-            {
-                continue;
-            }
-            self.prev_token = next;
-            break next;
-        })
+        let next = self.peeked.pop_front().or_else(|| self.next_from_lexer());
+        self.prev_token = next;
+        next
     }
 }
 
 impl<'a> TokenStream<'a> {
     /// Creates a new `TokenStream` wrapping the given lexer.
     pub fn new(lexer: Lexer<'a>) -> Self {
+        Self::with_tokens(lexer, lexer.collect())
+    }
+
+    /// Creates a new `TokenStream` over tokens of the given lexer, like the ones returned by
+    /// [`resolve_params`](crate::ast::param::resolve_params)
+    pub fn with_tokens(lexer: Lexer<'a>, tokens: Vec<Token<'a>>) -> Self {
         Self {
             peeked: VecDeque::new(),
             lexer,
+            tokens: tokens.into_iter(),
             prev_token: None,
+            last_lexed: None,
+            lookahead: None,
+            bracket_depth: 0,
+        }
+    }
+
+    /// Takes the next token from the lexer, skipping newlines that follow another newline or are
+    /// inside of brackets, and commas directly before a closing bracket.
+    fn next_from_lexer(&mut self) -> Option<Token<'a>> {
+        loop {
+            let next = self.lookahead.take().or_else(|| self.tokens.next());
+            let next_type = next.map(|t| t.kind());
+            match next_type {
+                Some(TokenType::Newline)
+                    if self.last_lexed == Some(TokenType::Newline) || self.bracket_depth > 0 =>
+                {
+                    continue;
+                }
+                Some(TokenType::LParen) | Some(TokenType::LSquare) => self.bracket_depth += 1,
+                Some(TokenType::RParen) | Some(TokenType::RSquare) => {
+                    self.bracket_depth = self.bracket_depth.saturating_sub(1)
+                }
+                Some(TokenType::Comma) => {
+                    self.lookahead = self
+                        .tokens
+                        .find(|t| t.kind() != TokenType::Newline || self.bracket_depth == 0);
+                    let after = self.lookahead.map(|t| t.kind());
+                    if matches!(
+                        after,
+                        Some(TokenType::RParen)
+                            | Some(TokenType::RSquare)
+                            | Some(TokenType::RCurly)
+                    ) {
+                        continue;
+                    }
+                }
+                _ => (),
+            }
+            self.last_lexed = next_type;
+            break next;
         }
     }
 
@@ -156,7 +270,7 @@ impl<'a> TokenStream<'a> {
         assert!(forward != 0);
 
         for _ in self.peeked.len()..forward {
-            let next = self.next()?;
+            let next = self.next_from_lexer()?;
             self.peeked.push_back(next);
         }
         self.peeked.get(forward - 1)
@@ -234,3 +348,70 @@ pub fn expect_tokens<'a, const N: usize>(
 
     Ok(out.map(Option::unwrap))
 }
+
+#[cfg(test)]
+mod parse_tests {
+    use super::*;
+    use crate::ast::{key::KeyRef, Profile, Trigger};
+
+    fn parse_profile(source: &str) -> miette::Result<Profile> {
+        Profile::parse(&mut TokenStream::new(Lexer::new("test", source)))
+    }
+
+    #[test]
+    fn test_brackets_span_lines() {
+        let keys = |source: &str| {
+            let mut ts = TokenStream::new(Lexer::new("test", source));
+            parse_square_bracket_list::<KeyRef>(&mut ts).map(|keys| keys.len())
+        };
+        assert_eq!(keys("[a, b]").unwrap(), 2);
+        assert_eq!(keys("[\n    a,\n    b,\n]").unwrap(), 2);
+        assert_eq!(keys("[a,\n\n b]").unwrap(), 2);
+        assert_eq!(
+            keys("[a,, b]").unwrap_err().to_string(),
+            "Unexpected token found"
+        );
+        assert_eq!(
+            keys("[a b]").unwrap_err().to_string(),
+            "Unexpected token found"
+        );
+    }
+
+    #[test]
+    fn test_free_form_blocks() {
+        let profile = parse_profile(
+            r#"profile "Test"
+config { tap_timeout = 150 }
+layer "base"
+{
+    chord([
+        a,
+        b,
+    ], capture,) =
+        [c, d,]
+    e = f
+}
+"#,
+        )
+        .unwrap();
+        let statements = &profile.layers[0].statements;
+        assert_eq!(statements.len(), 2);
+        let Trigger::Chord(keys, ..) = &statements[0].lhs.value else {
+            panic!("expected a chord");
+        };
+        assert_eq!(keys.len(), 2);
+        assert_eq!(statements[0].rhs.len(), 2);
+
+        let error = parse_profile(
+            r#"profile "Test"
+config { }
+layer "base" {
+    a
+    = b
+}
+"#,
+        )
+        .unwrap_err();
+        assert_eq!(error.to_string(), "Unexpected token found");
+    }
+}
@@ -0,0 +1,46 @@
+//! The operating systems a profile can be compiled for
+use core::fmt::{self, Display};
+use std::{fmt::Formatter, str::FromStr};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, clap::ValueEnum)]
+pub enum TargetOs {
+    Linux,
+    Macos,
+    Windows,
+}
+
+impl TargetOs {
+    /// The operating system the CLI is running on
+    pub fn host() -> Self {
+        if cfg!(target_os = "windows") {
+            TargetOs::Windows
+        } else if cfg!(target_os = "macos") {
+            TargetOs::Macos
+        } else {
+            TargetOs::Linux
+        }
+    }
+}
+
+impl FromStr for TargetOs {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "linux" => Ok(TargetOs::Linux),
+            "macos" => Ok(TargetOs::Macos),
+            "windows" => Ok(TargetOs::Windows),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Display for TargetOs {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            TargetOs::Linux => write!(f, "linux"),
+            TargetOs::Macos => write!(f, "macos"),
+            TargetOs::Windows => write!(f, "windows"),
+        }
+    }
+}
@@ -9,6 +9,17 @@ pub fn exit_with_error(err: miette::Error) -> ! {
     exit(1);
 }
 
+/// 64 bit FNV-1a hash. Unlike the std hasher its output is stable across builds, so it can be
+/// stored and compared later.
+pub fn fnv1a_hash(bytes: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    bytes.iter().fold(OFFSET_BASIS, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(PRIME)
+    })
+}
+
 /// Represents a span of source code.
 ///
 /// Empty spans are allowed
@@ -54,6 +65,11 @@ impl Span {
         self.len
     }
 
+    /// Returns true if the span has a length of zero
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
     /// Returns the end (exclusive) of the span
     pub fn end(&self) -> usize {
         self.start + self.len